@@ -1,34 +1,83 @@
-use clap::{app_from_crate, crate_authors, crate_description, crate_name, crate_version};
-use clap::{App, Arg, ArgMatches};
+use clap::{crate_description, crate_name, crate_version};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use csv2json::dialect::{parse_byte, parse_delimiter};
+use csv2json::encoding::Encoding;
+use csv2json::types::{parse_timezone, NumberFormat};
+use csv2json::{Selector, Terminator};
 
 pub const DELIMITER: &str = "delimiter";
+pub const QUOTE: &str = "quote";
+pub const ESCAPE: &str = "escape";
+pub const NO_DOUBLE_QUOTE: &str = "no-double-quote";
+pub const NO_QUOTING: &str = "no-quoting";
+pub const COMMENT: &str = "comment";
+pub const TRIM: &str = "trim";
+pub const TERMINATOR: &str = "terminator";
+pub const NO_HEADERS: &str = "no-headers";
+pub const FLEXIBLE: &str = "flexible";
+pub const SNIFF: &str = "sniff";
+pub const ENCODING: &str = "encoding";
 pub const DIMENSIONAL_SEPARATOR: &str = "dimensional-separator";
 pub const ARRAYS: &str = "arrays";
+pub const POSITIONAL_ARRAYS: &str = "positional-arrays";
 pub const REMOVE_EMPTY_STRINGS: &str = "remove-empty-strings";
 pub const REMOVE_EMPTY_OBJECTS: &str = "remove-empty-objects";
+pub const REMOVE_NULLS: &str = "remove-nulls";
+pub const NULL_VALUES: &str = "null-values";
+pub const NULLABLE: &str = "nullable";
+pub const EMPTY_AS_NULL: &str = "empty-as-null";
 pub const IN: &str = "in";
 pub const OUT_DIR: &str = "out-dir";
 pub const OUT_NAME: &str = "out-name";
 pub const JSONL: &str = "jsonl";
 pub const BOOLEAN: &str = "boolean";
 pub const NUMERIC: &str = "numeric";
+pub const INTEGER: &str = "integer";
+pub const FLOAT: &str = "float";
+pub const DECIMAL: &str = "decimal";
+pub const BOOLEAN_REGEX: &str = "boolean-regex";
+pub const NUMERIC_REGEX: &str = "numeric-regex";
+pub const INTEGER_REGEX: &str = "integer-regex";
+pub const FLOAT_REGEX: &str = "float-regex";
+pub const DECIMAL_REGEX: &str = "decimal-regex";
+pub const ON_TYPE_ERROR: &str = "on-type-error";
+pub const TYPE_ERROR_DEFAULT: &str = "type-error-default";
+pub const TRUE_VALUES: &str = "true-values";
+pub const FALSE_VALUES: &str = "false-values";
+pub const LENIENT_BOOLEANS: &str = "lenient-booleans";
+pub const EMPTY_BOOLEAN: &str = "empty-boolean";
+pub const NUMBER_LOCALE: &str = "number-locale";
+pub const DECIMAL_SEPARATOR: &str = "decimal-separator";
+pub const THOUSANDS_SEPARATOR: &str = "thousands-separator";
+pub const DIVIDE_PERCENTAGES: &str = "divide-percentages";
+pub const DATE: &str = "date";
+pub const DATETIME: &str = "datetime";
+pub const DATE_OUTPUT: &str = "date-output";
+pub const TIMEZONE: &str = "timezone";
+pub const SCHEMA: &str = "schema";
+pub const INFER_TYPES: &str = "infer-types";
+pub const TYPED_HEADERS: &str = "typed-headers";
+pub const INFER_SAMPLE: &str = "infer-sample";
 pub const FOLD: &str = "fold";
+pub const SORT_KEYS: &str = "sort-keys";
+pub const ON_ERROR: &str = "on-error";
+pub const REJECTS: &str = "rejects";
+pub const EMIT_JSON_SCHEMA: &str = "emit-json-schema";
+pub const VALIDATE: &str = "validate";
+pub const INFER_SCHEMA: &str = "infer-schema";
+pub const JSON2CSV: &str = "json2csv";
+pub const OUT: &str = "out";
 
 pub fn get_matches<'a>() -> ArgMatches<'a> {
     configure_app().get_matches()
 }
 
 fn configure_app<'a, 'b>() -> App<'a, 'b> {
-    app_from_crate!("\n")
-        .arg(
-            Arg::with_name(IN)
-                .short("i")
-                .long(IN)
-                .value_name("FILE")
-                .help("The csv file to read, otherwise reads stdin")
-                .takes_value(true)
-                .required(false),
-        )
+    App::new(crate_name!())
+        .version(crate_version!())
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about(crate_description!())
+        .arg(in_arg())
         .arg(
             Arg::with_name(OUT_DIR)
                 .short("o")
@@ -53,23 +102,15 @@ fn configure_app<'a, 'b>() -> App<'a, 'b> {
                 .help("Output JSONL: one line per record")
                 .takes_value(false),
         )
+        .arg(delimiter_arg())
+        .args(&dialect_args())
         .arg(
-            Arg::with_name(DELIMITER)
-                .short("d")
-                .long(DELIMITER)
-                .value_name("DELIMITER")
-                .help("What delimiter does your csv use")
-                .takes_value(true)
-                .default_value(","),
-        )
-        .arg(
-            Arg::with_name(DIMENSIONAL_SEPARATOR)
-                .short("D")
-                .long(DIMENSIONAL_SEPARATOR)
-                .value_name("SEPARATOR")
-                .help("A separator to break header names allowing you to create deeper objects")
-                .takes_value(true),
+            Arg::with_name(FLEXIBLE)
+                .long(FLEXIBLE)
+                .help("Convert rows with missing fields, which are empty, or extra fields, which are dropped")
+                .takes_value(false),
         )
+        .arg(dimensional_separator_arg())
         .arg(
             Arg::with_name(ARRAYS)
                 .short("a")
@@ -77,6 +118,12 @@ fn configure_app<'a, 'b>() -> App<'a, 'b> {
                 .help("Indicates the csv contains arrays represented by numeric keys. Use with -D")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name(POSITIONAL_ARRAYS)
+                .long(POSITIONAL_ARRAYS)
+                .help("Like --arrays but puts items at the position of their key, filling gaps with null")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name(BOOLEAN)
                 .short("b")
@@ -86,6 +133,37 @@ fn configure_app<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(true)
                 .multiple(true),
         )
+        .arg(
+            Arg::with_name(TRUE_VALUES)
+                .long(TRUE_VALUES)
+                .value_name("VALUES")
+                .help("The comma separated values that are true in boolean columns [default: true,yes,y,on,1]")
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name(FALSE_VALUES)
+                .long(FALSE_VALUES)
+                .value_name("VALUES")
+                .help("The comma separated values that are false in boolean columns [default: false,no,n,off,0]")
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name(LENIENT_BOOLEANS)
+                .long(LENIENT_BOOLEANS)
+                .help("Values in boolean columns that are neither true nor false are true, otherwise they do not fit")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name(EMPTY_BOOLEAN)
+                .long(EMPTY_BOOLEAN)
+                .value_name("VALUE")
+                .help("What empty values in boolean columns become")
+                .takes_value(true)
+                .possible_values(&["false", "null"])
+                .default_value("false"),
+        )
         .arg(
             Arg::with_name(NUMERIC)
                 .short("n")
@@ -95,6 +173,158 @@ fn configure_app<'a, 'b>() -> App<'a, 'b> {
                 .takes_value(true)
                 .multiple(true),
         )
+        .arg(
+            Arg::with_name(INTEGER)
+                .long(INTEGER)
+                .value_name("COLUMN")
+                .help("Indicate that COLUMN is a whole number")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name(FLOAT)
+                .long(FLOAT)
+                .value_name("COLUMN")
+                .help("Indicate that COLUMN is a number that is always written with a decimal point")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name(DECIMAL)
+                .long(DECIMAL)
+                .value_name("COLUMN")
+                .help("Indicate that COLUMN is a number that should be written exactly, without rounding")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(regex_arg(BOOLEAN_REGEX).help("Indicate that columns matching REGEX are booleans"))
+        .arg(regex_arg(NUMERIC_REGEX).help("Indicate that columns matching REGEX are numeric"))
+        .arg(regex_arg(INTEGER_REGEX).help("Indicate that columns matching REGEX are whole numbers"))
+        .arg(regex_arg(FLOAT_REGEX).help("Indicate that columns matching REGEX are floats"))
+        .arg(regex_arg(DECIMAL_REGEX).help("Indicate that columns matching REGEX are decimals"))
+        .arg(
+            Arg::with_name(ON_TYPE_ERROR)
+                .long(ON_TYPE_ERROR)
+                .value_name("POLICY")
+                .help("Whether values that do not fit their type fail the row, become null, are kept as strings or become the default")
+                .takes_value(true)
+                .possible_values(&["fail", "null", "keep", "default"])
+                .default_value("fail"),
+        )
+        .arg(
+            Arg::with_name(TYPE_ERROR_DEFAULT)
+                .long(TYPE_ERROR_DEFAULT)
+                .value_name("VALUE")
+                .help("The json value for --on-type-error default, which is implied if it is not given")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(NUMBER_LOCALE)
+                .long(NUMBER_LOCALE)
+                .value_name("LOCALE")
+                .help("Read numbers with the decimal and thousands separators of LOCALE, such as de or fr_FR")
+                .takes_value(true)
+                .validator(|locale| NumberFormat::from_locale(&locale).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name(DECIMAL_SEPARATOR)
+                .long(DECIMAL_SEPARATOR)
+                .value_name("CHAR")
+                .help("The character between a whole number and its decimals, instead of the locale's")
+                .takes_value(true)
+                .validator(single_char),
+        )
+        .arg(
+            Arg::with_name(THOUSANDS_SEPARATOR)
+                .long(THOUSANDS_SEPARATOR)
+                .value_name("CHAR")
+                .help("The character between groups of digits, instead of the locale's")
+                .takes_value(true)
+                .validator(single_char),
+        )
+        .arg(
+            Arg::with_name(DIVIDE_PERCENTAGES)
+                .long(DIVIDE_PERCENTAGES)
+                .help("Divide numbers ending in % by 100")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name(DATE)
+                .long(DATE)
+                .value_name("COLUMN[=FORMAT]")
+                .help("Indicate that COLUMN is a date, in any of the strftime FORMATs separated by |")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name(DATETIME)
+                .long(DATETIME)
+                .value_name("COLUMN[=FORMAT]")
+                .help("Indicate that COLUMN is a date and time, in any of the strftime FORMATs separated by |")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name(DATE_OUTPUT)
+                .long(DATE_OUTPUT)
+                .value_name("OUTPUT")
+                .help("Write dates as ISO 8601 or as seconds or milliseconds since the Unix epoch")
+                .takes_value(true)
+                .possible_values(&["iso", "seconds", "milliseconds"])
+                .default_value("iso"),
+        )
+        .arg(
+            Arg::with_name(TIMEZONE)
+                .long(TIMEZONE)
+                .value_name("TIMEZONE")
+                .help("The timezone of datetimes without one, UTC or an offset such as +02:00")
+                .takes_value(true)
+                .validator(|timezone| parse_timezone(&timezone).map(|_| ())),
+        )
+        .arg(
+            Arg::with_name(SCHEMA)
+                .short("s")
+                .long(SCHEMA)
+                .value_name("FILE")
+                .help("A json or toml file describing the type, path and default of each column")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(INFER_TYPES)
+                .long(INFER_TYPES)
+                .help("Work out the type of columns that are not given one")
+                .takes_value(false),
+        )
+        .arg(infer_sample_arg())
+        .arg(typed_headers_arg())
+        .arg(
+            Arg::with_name(NULL_VALUES)
+                .long(NULL_VALUES)
+                .value_name("VALUES")
+                .help("The comma separated values that are null in any column, such as NA,N/A,NULL")
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .arg(
+            Arg::with_name(NULLABLE)
+                .long(NULLABLE)
+                .value_name("COLUMN")
+                .help("Empty values in COLUMN are null")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name(EMPTY_AS_NULL)
+                .long(EMPTY_AS_NULL)
+                .help("Empty values in every column are null")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name(REMOVE_EMPTY_STRINGS)
                 .long(REMOVE_EMPTY_STRINGS)
@@ -109,6 +339,12 @@ fn configure_app<'a, 'b>() -> App<'a, 'b> {
                 .help("Removes keys that contain empty objects")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name(REMOVE_NULLS)
+                .long(REMOVE_NULLS)
+                .help("Removes keys that are null")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name(FOLD)
                 .long(FOLD)
@@ -117,6 +353,221 @@ fn configure_app<'a, 'b>() -> App<'a, 'b> {
                 .help("Fold array of json objects into one object with an array for each column")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name(SORT_KEYS)
+                .long(SORT_KEYS)
+                .help("Sort keys alphabetically instead of keeping the order of the columns")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name(ON_ERROR)
+                .long(ON_ERROR)
+                .value_name("POLICY")
+                .help("What to do with rows that can not be converted")
+                .takes_value(true)
+                .possible_values(&["skip", "warn", "fail"])
+                .default_value("warn"),
+        )
+        .arg(
+            Arg::with_name(REJECTS)
+                .long(REJECTS)
+                .value_name("FILE")
+                .help("Write rows that can not be converted to FILE as csv")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(EMIT_JSON_SCHEMA)
+                .long(EMIT_JSON_SCHEMA)
+                .value_name("FILE")
+                .help("Write a JSON Schema describing the output to FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(VALIDATE)
+                .long(VALIDATE)
+                .value_name("SCHEMA")
+                .help("Check each row, or the folded json, against a JSON Schema (draft 2020-12)")
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name(INFER_SCHEMA)
+                .about("Work out a schema for the csv that can be used with --schema")
+                .arg(in_arg())
+                .arg(delimiter_arg())
+                .args(&dialect_args())
+                .arg(dimensional_separator_arg())
+                .arg(infer_sample_arg())
+                .arg(typed_headers_arg())
+                .arg(
+                    Arg::with_name(OUT)
+                        .short("o")
+                        .long(OUT)
+                        .value_name("FILE")
+                        .help("Where to write the schema, as toml if it ends in .toml, otherwise json")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(JSON2CSV)
+                .about("Convert a json array, jsonl or folded json back into csv")
+                .arg(
+                    Arg::with_name(IN)
+                        .short("i")
+                        .long(IN)
+                        .value_name("FILE")
+                        .help("The json file to read, otherwise reads stdin")
+                        .takes_value(true),
+                )
+                .arg(delimiter_arg())
+                .arg(dimensional_separator_arg())
+                .arg(
+                    Arg::with_name(FOLD)
+                        .long(FOLD)
+                        .short("F")
+                        .help("The json is folded, even if it only has one column")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name(OUT)
+                        .short("o")
+                        .long(OUT)
+                        .value_name("FILE")
+                        .help("Where to write the csv, otherwise writes to stdout")
+                        .takes_value(true),
+                ),
+        )
+}
+
+// Arguments shared by the conversion and the subcommands
+
+fn in_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(IN)
+        .short("i")
+        .long(IN)
+        .value_name("FILE")
+        .help("The csv file to read, otherwise reads stdin")
+        .takes_value(true)
+        .required(false)
+}
+
+fn delimiter_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(DELIMITER)
+        .short("d")
+        .long(DELIMITER)
+        .value_name("DELIMITER")
+        .help("What delimiter does your csv use: a character, an escape such as \\t or \\x1f, tab, or one of the presets csv, tsv and psv")
+        .takes_value(true)
+        .default_value(",")
+        .validator(|delimiter| parse_delimiter(&delimiter).map(|_| ()))
+}
+
+// How the csv is quoted, escaped and so on, as well as its delimiter
+fn dialect_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name(QUOTE)
+            .long(QUOTE)
+            .value_name("CHAR")
+            .help("The character fields are quoted with")
+            .takes_value(true)
+            .default_value("\"")
+            .validator(|value| parse_byte(&value).map(|_| ())),
+        Arg::with_name(ESCAPE)
+            .long(ESCAPE)
+            .value_name("CHAR")
+            .help("The character that escapes quotes in quoted fields, such as \\")
+            .takes_value(true)
+            .validator(|value| parse_byte(&value).map(|_| ())),
+        Arg::with_name(NO_DOUBLE_QUOTE)
+            .long(NO_DOUBLE_QUOTE)
+            .help("Do not read two quotes in a quoted field as one")
+            .takes_value(false),
+        Arg::with_name(NO_QUOTING)
+            .long(NO_QUOTING)
+            .help("Read quotes like any other character")
+            .takes_value(false),
+        Arg::with_name(COMMENT)
+            .long(COMMENT)
+            .value_name("CHAR")
+            .help("Skip lines that start with CHAR, such as #")
+            .takes_value(true)
+            .validator(|value| parse_byte(&value).map(|_| ())),
+        Arg::with_name(TRIM)
+            .long(TRIM)
+            .value_name("WHAT")
+            .help("Remove whitespace from around headers, fields or both")
+            .takes_value(true)
+            .possible_values(&["none", "headers", "fields", "all"])
+            .default_value("none"),
+        Arg::with_name(ENCODING)
+            .long(ENCODING)
+            .value_name("ENCODING")
+            .help("The encoding of the csv: utf-8, utf-16le, utf-16be, windows-1252 or auto to detect it")
+            .takes_value(true)
+            .default_value("utf-8")
+            .validator(|encoding| match encoding.as_str() {
+                "auto" => Ok(()),
+                encoding => encoding.parse::<Encoding>().map(|_| ()),
+            }),
+        Arg::with_name(NO_HEADERS)
+            .long(NO_HEADERS)
+            .help("The first row is not headers, name the columns column1, column2 and so on")
+            .takes_value(false),
+        Arg::with_name(SNIFF)
+            .long(SNIFF)
+            .help("Work out the delimiter, quote and terminator from the start of the csv, and whether it has headers, printing them on stderr")
+            .takes_value(false),
+        Arg::with_name(TERMINATOR)
+            .long(TERMINATOR)
+            .value_name("TERMINATOR")
+            .help("What ends each record: crlf for any line ending, cr, lf or a character")
+            .takes_value(true)
+            .default_value("crlf")
+            .validator(|terminator| terminator.parse::<Terminator>().map(|_| ())),
+    ]
+}
+
+fn dimensional_separator_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(DIMENSIONAL_SEPARATOR)
+        .short("D")
+        .long(DIMENSIONAL_SEPARATOR)
+        .value_name("SEPARATOR")
+        .help("A separator to break header names allowing you to create deeper objects")
+        .takes_value(true)
+}
+
+// Like the option for a type but matching columns with a regular expression
+fn regex_arg<'a, 'b>(name: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .long(name)
+        .value_name("REGEX")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .validator(|regex| Selector::regex(&regex).map(|_| ()))
+}
+
+fn single_char(value: String) -> Result<(), String> {
+    if value.chars().count() == 1 {
+        Ok(())
+    } else {
+        Err(format!("{:?} is not a single character", value))
+    }
+}
+
+fn infer_sample_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(INFER_SAMPLE)
+        .long(INFER_SAMPLE)
+        .value_name("ROWS")
+        .help("How many rows to infer types from, 0 for the whole file")
+        .takes_value(true)
+        .default_value("1000")
+}
+
+fn typed_headers_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(TYPED_HEADERS)
+        .long(TYPED_HEADERS)
+        .help("Take the type of columns from headers such as age:integer, removing it from the key")
+        .takes_value(false)
 }
 
 #[cfg(test)]
@@ -126,4 +577,23 @@ mod tests {
         let app = super::configure_app();
         assert_eq!(app.get_name(), "csv2json");
     }
+
+    #[test]
+    fn it_reads_infer_schema_options() {
+        let matches = super::configure_app().get_matches_from(vec![
+            "csv2json",
+            "infer-schema",
+            "--in",
+            "test.csv",
+            "-D",
+            ".",
+            "--out",
+            "schema.toml",
+        ]);
+        let matches = matches.subcommand_matches(super::INFER_SCHEMA).unwrap();
+        assert_eq!(matches.value_of(super::IN), Some("test.csv"));
+        assert_eq!(matches.value_of(super::DIMENSIONAL_SEPARATOR), Some("."));
+        assert_eq!(matches.value_of(super::DELIMITER), Some(","));
+        assert_eq!(matches.value_of(super::OUT), Some("schema.toml"));
+    }
 }
@@ -0,0 +1,2068 @@
+use chrono::FixedOffset;
+use data::{self, ArrayMode};
+use dialect::{Dialect, Terminator, Trim};
+use error::{Error, ErrorPolicy, Rejection, Result, Warning};
+use infer::TypeInference;
+use json_schema;
+use schema::{ColumnSchema, Schema};
+use selector::{self, Selector};
+use serde::ser::{SerializeSeq, Serializer};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufWriter, Read, Write};
+use std::mem;
+use types::{
+    self, BooleanFormat, ColumnType, DateOutput, NumberFormat, TypeErrorPolicy, TypeSettings,
+};
+
+/// Options controlling how a csv is turned into json, mirroring the command line flags
+///
+/// ```
+/// use csv2json::ConvertOptions;
+///
+/// let converter = ConvertOptions::new()
+///     .dimensional_separator(".")
+///     .numeric_column("age")
+///     .build();
+/// let json = converter.convert("name.first,age\nDaniel,34\n".as_bytes()).unwrap();
+/// assert_eq!(json[0]["name"]["first"], "Daniel");
+/// assert_eq!(json[0]["age"], 34);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    dialect: Dialect,
+    has_headers: bool,
+    flexible: bool,
+    dimensional_separator: Option<String>,
+    arrays: bool,
+    array_mode: ArrayMode,
+    boolean_columns: Vec<Selector>,
+    numeric_columns: Vec<Selector>,
+    integer_columns: Vec<Selector>,
+    float_columns: Vec<Selector>,
+    decimal_columns: Vec<Selector>,
+    date_columns: Vec<(Selector, Vec<String>)>,
+    datetime_columns: Vec<(Selector, Vec<String>)>,
+    types: TypeSettings,
+    infer_types: bool,
+    infer_sample: usize,
+    schema: Schema,
+    typed_headers: bool,
+    nullable_columns: Vec<Selector>,
+    empty_as_null: bool,
+    remove_empty_strings: bool,
+    remove_empty_objects: bool,
+    remove_nulls: bool,
+    fold: bool,
+    jsonl: bool,
+    sort_keys: bool,
+    on_error: ErrorPolicy,
+    validate: Option<Value>,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            dialect: Dialect::default(),
+            has_headers: true,
+            flexible: false,
+            dimensional_separator: None,
+            arrays: false,
+            array_mode: ArrayMode::Ordered,
+            boolean_columns: vec![],
+            numeric_columns: vec![],
+            integer_columns: vec![],
+            float_columns: vec![],
+            decimal_columns: vec![],
+            date_columns: vec![],
+            datetime_columns: vec![],
+            types: TypeSettings::default(),
+            infer_types: false,
+            infer_sample: 1000,
+            schema: Schema::default(),
+            typed_headers: false,
+            nullable_columns: vec![],
+            empty_as_null: false,
+            remove_empty_strings: false,
+            remove_empty_objects: false,
+            remove_nulls: false,
+            fold: false,
+            jsonl: false,
+            sort_keys: false,
+            on_error: ErrorPolicy::default(),
+            validate: None,
+        }
+    }
+}
+
+impl ConvertOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How the csv is quoted, escaped and so on, see [`Dialect`]
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// The byte separating columns, `,` by default
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.dialect.delimiter = delimiter;
+        self
+    }
+
+    /// The byte fields are quoted with, `"` by default
+    pub fn quote(mut self, quote: u8) -> Self {
+        self.dialect.quote = quote;
+        self
+    }
+
+    /// Read quotes inside quoted fields that are escaped with this byte, such as `\`
+    pub fn escape(mut self, escape: u8) -> Self {
+        self.dialect.escape = Some(escape);
+        self
+    }
+
+    /// Whether a quote inside a quoted field can be written twice, `""`, which is the default
+    pub fn double_quote(mut self, double_quote: bool) -> Self {
+        self.dialect.double_quote = double_quote;
+        self
+    }
+
+    /// Whether quotes are special, which is the default, or read like any other character
+    pub fn quoting(mut self, quoting: bool) -> Self {
+        self.dialect.quoting = quoting;
+        self
+    }
+
+    /// Skip lines that start with this byte, such as `#`
+    pub fn comment(mut self, comment: u8) -> Self {
+        self.dialect.comment = Some(comment);
+        self
+    }
+
+    /// Remove whitespace from around headers, fields or both
+    pub fn trim(mut self, trim: Trim) -> Self {
+        self.dialect.trim = trim;
+        self
+    }
+
+    /// What ends each record, by default any of `\r\n`, `\n` or `\r`
+    pub fn terminator(mut self, terminator: Terminator) -> Self {
+        self.dialect.terminator = terminator;
+        self
+    }
+
+    /// Whether the first row is headers, otherwise the columns are named `column1`, `column2` and
+    /// so on, as many as there are fields in the first row
+    pub fn has_headers(mut self, has_headers: bool) -> Self {
+        self.has_headers = has_headers;
+        self
+    }
+
+    /// Convert rows that do not have a field for every header, the missing fields are empty and
+    /// extra fields are dropped
+    ///
+    /// By default they can not be converted and are handled by [`ConvertOptions::on_error`].
+    pub fn flexible(mut self, flexible: bool) -> Self {
+        self.flexible = flexible;
+        self
+    }
+
+    /// Break header names on this separator to create deeper objects
+    pub fn dimensional_separator<S: Into<String>>(mut self, separator: S) -> Self {
+        self.dimensional_separator = Some(separator.into());
+        self
+    }
+
+    /// Turn objects that only have numeric keys into arrays
+    pub fn arrays(mut self, arrays: bool) -> Self {
+        self.arrays = arrays;
+        self
+    }
+
+    /// How numeric keys are turned into arrays, they are sorted by default
+    pub fn array_mode(mut self, mode: ArrayMode) -> Self {
+        self.array_mode = mode;
+        self
+    }
+
+    /// Treat the given column as a boolean
+    pub fn boolean_column<S: Into<Selector>>(mut self, column: S) -> Self {
+        self.boolean_columns.push(column.into());
+        self
+    }
+
+    /// Treat each of the given columns as booleans
+    pub fn boolean_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Selector>,
+    {
+        self.boolean_columns
+            .extend(columns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Treat the given column as a number
+    pub fn numeric_column<S: Into<Selector>>(mut self, column: S) -> Self {
+        self.numeric_columns.push(column.into());
+        self
+    }
+
+    /// Treat each of the given columns as numbers
+    pub fn numeric_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Selector>,
+    {
+        self.numeric_columns
+            .extend(columns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Treat each of the given columns as whole numbers, anything else does not fit
+    pub fn integer_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Selector>,
+    {
+        self.integer_columns
+            .extend(columns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Treat each of the given columns as numbers that are always written with a decimal point
+    pub fn float_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Selector>,
+    {
+        self.float_columns
+            .extend(columns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Treat each of the given columns as numbers that are written exactly as they are in the
+    /// csv, such as amounts of money that would be rounded as a float
+    pub fn decimal_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Selector>,
+    {
+        self.decimal_columns
+            .extend(columns.into_iter().map(Into::into));
+        self
+    }
+
+    /// What to do with values that do not fit the type of their column
+    ///
+    /// By default the row can not be converted and is handled by [`ConvertOptions::on_error`].
+    /// Columns in the schema can choose for themselves.
+    pub fn on_type_error(mut self, policy: TypeErrorPolicy) -> Self {
+        self.types.on_type_error = policy;
+        self
+    }
+
+    /// The value [`TypeErrorPolicy::Default`] uses for columns without a default, null unless
+    /// it is set
+    pub fn type_error_default(mut self, value: Value) -> Self {
+        self.types.type_error_default = value;
+        self
+    }
+
+    /// Treat the given column as a date in any of the strftime formats, or `%Y-%m-%d` if there are
+    /// none
+    pub fn date_column<S, I, F>(mut self, column: S, formats: I) -> Self
+    where
+        S: Into<Selector>,
+        I: IntoIterator<Item = F>,
+        F: Into<String>,
+    {
+        let formats = formats.into_iter().map(Into::into).collect();
+        self.date_columns.push((column.into(), formats));
+        self
+    }
+
+    /// Treat the given column as a date and time in any of the strftime formats, or ISO 8601 if
+    /// there are none
+    pub fn datetime_column<S, I, F>(mut self, column: S, formats: I) -> Self
+    where
+        S: Into<Selector>,
+        I: IntoIterator<Item = F>,
+        F: Into<String>,
+    {
+        let formats = formats.into_iter().map(Into::into).collect();
+        self.datetime_columns.push((column.into(), formats));
+        self
+    }
+
+    /// Write dates and datetimes as ISO 8601, which is the default, or as a Unix timestamp
+    pub fn date_output(mut self, output: DateOutput) -> Self {
+        self.types.dates.output = output;
+        self
+    }
+
+    /// The timezone of datetimes that do not have one
+    ///
+    /// Without a timezone they are written without an offset, or as UTC when written as a Unix
+    /// timestamp.
+    pub fn timezone(mut self, timezone: FixedOffset) -> Self {
+        self.types.dates.timezone = Some(timezone);
+        self
+    }
+
+    /// How numbers are written in integer, number, float and decimal columns, such as the
+    /// separators of a locale
+    pub fn number_format(mut self, format: NumberFormat) -> Self {
+        self.types.numbers = format;
+        self
+    }
+
+    /// The values that are true and false in boolean columns, and what empty values are
+    pub fn boolean_format(mut self, format: BooleanFormat) -> Self {
+        self.types.booleans = format;
+        self
+    }
+
+    /// Work out the type of columns that are not given one, see [`TypeInference`]
+    ///
+    /// Empty values in these columns become null.
+    pub fn infer_types(mut self, infer: bool) -> Self {
+        self.infer_types = infer;
+        self
+    }
+
+    /// How many rows to look at when inferring types, 1000 by default or 0 for the whole csv
+    ///
+    /// The sampled rows are held in memory until they are converted.
+    pub fn infer_sample(mut self, rows: usize) -> Self {
+        self.infer_sample = rows;
+        self
+    }
+
+    /// Convert columns as described by the schema
+    ///
+    /// Columns given with [`ConvertOptions::numeric_column`] or
+    /// [`ConvertOptions::boolean_column`] take that type instead of the one in the schema. The
+    /// schema's dimensional separator is used if one is not given.
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// Take the type of columns from their headers, such as `age:integer` or `active:bool`
+    ///
+    /// The type is removed from the header, see [`split_typed_header`](types::split_typed_header).
+    /// These types win over the schema, but not over types given on their own.
+    pub fn typed_headers(mut self, typed_headers: bool) -> Self {
+        self.typed_headers = typed_headers;
+        self
+    }
+
+    /// Values that are null in any column, such as `NA` or `N/A`
+    ///
+    /// These are replaced before converting types, so they are null whatever the type.
+    pub fn null_values<I, S>(mut self, values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.types
+            .null_values
+            .extend(values.into_iter().map(Into::into));
+        self
+    }
+
+    /// Make empty values in each of the given columns null, unless the schema gives a default
+    pub fn nullable_columns<I, S>(mut self, columns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Selector>,
+    {
+        self.nullable_columns
+            .extend(columns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Make empty values in every column null, unless the schema gives a default
+    pub fn empty_as_null(mut self, empty_as_null: bool) -> Self {
+        self.empty_as_null = empty_as_null;
+        self
+    }
+
+    /// Remove keys and array items that contain empty strings
+    pub fn remove_empty_strings(mut self, remove: bool) -> Self {
+        self.remove_empty_strings = remove;
+        self
+    }
+
+    /// Remove keys and array items that contain empty objects
+    pub fn remove_empty_objects(mut self, remove: bool) -> Self {
+        self.remove_empty_objects = remove;
+        self
+    }
+
+    /// Remove keys that are null, array items are kept so that they stay in position
+    pub fn remove_nulls(mut self, remove: bool) -> Self {
+        self.remove_nulls = remove;
+        self
+    }
+
+    /// Fold the array of row objects into one object with an array for each column
+    ///
+    /// When converting json back into csv, the json is always read as folded. Otherwise it is only
+    /// when it is an object of more than one array of the same length.
+    pub fn fold(mut self, fold: bool) -> Self {
+        self.fold = fold;
+        self
+    }
+
+    /// Write one json document per line instead of a pretty printed array
+    pub fn jsonl(mut self, jsonl: bool) -> Self {
+        self.jsonl = jsonl;
+        self
+    }
+
+    /// Sort the keys of every object alphabetically instead of keeping the order of the columns
+    pub fn sort_keys(mut self, sort_keys: bool) -> Self {
+        self.sort_keys = sort_keys;
+        self
+    }
+
+    /// What to do with records that can not be converted, they are reported as warnings by
+    /// default, see [`Rows::on_warning`]
+    pub fn on_error(mut self, policy: ErrorPolicy) -> Self {
+        self.on_error = policy;
+        self
+    }
+
+    /// Check each converted row, or the folded document, against a JSON Schema (draft 2020-12)
+    ///
+    /// A schema for an array of rows, such as the one from [`Rows::json_schema`], checks each row
+    /// against its `items`. Rows that do not match are handled by [`ConvertOptions::on_error`].
+    pub fn validate(mut self, json_schema: Value) -> Self {
+        self.validate = Some(json_schema);
+        self
+    }
+
+    pub fn build(mut self) -> Converter {
+        if self.dimensional_separator.is_none() {
+            self.dimensional_separator = self.schema.dimensional_separator.clone();
+        }
+        Converter { options: self }
+    }
+}
+
+/// A single converted row alongside the raw csv values it was built from
+#[derive(Clone, Debug, PartialEq)]
+pub struct Row {
+    pub raw: HashMap<String, String>,
+    pub value: Value,
+}
+
+/// Converts csv input into json using a fixed set of [`ConvertOptions`]
+#[derive(Clone, Debug)]
+pub struct Converter {
+    options: ConvertOptions,
+}
+
+impl Converter {
+    pub fn options(&self) -> &ConvertOptions {
+        &self.options
+    }
+
+    /// Convert the whole csv into a single json document
+    ///
+    /// This is an array with one object per row, or an object of arrays when folding.
+    pub fn convert<R: Read>(&self, reader: R) -> Result<Value> {
+        let mut rows = self.rows(reader)?;
+        if self.options.fold {
+            return self.fold(&mut rows);
+        }
+        let values = rows
+            .map(|row| row.map(|row| row.value))
+            .collect::<Result<Vec<Value>>>()?;
+        Ok(Value::Array(values))
+    }
+
+    /// Work out a schema for the csv from the first [`ConvertOptions::infer_sample`] rows
+    ///
+    /// Each column is given a type, whether it is nullable, how many distinct values it has and
+    /// a few examples. Malformed records are ignored. The schema records the dimensional
+    /// separator so converting with it nests the columns the same way.
+    pub fn infer_schema<R: Read>(&self, reader: R) -> Result<Schema> {
+        let mut csv_reader = self.csv_reader(reader);
+        let (headers, header_types) = self.read_headers(&mut csv_reader)?;
+        let mut inference = TypeInference::new(&headers, &self.options.types);
+        let mut record = csv::ByteRecord::new();
+        let mut sampled = 0;
+        while self.options.infer_sample == 0 || sampled < self.options.infer_sample {
+            if !csv_reader.read_byte_record(&mut record)? {
+                break;
+            }
+            sampled += 1;
+            if record.len() == headers.len() {
+                if let Ok(record) = csv::StringRecord::from_byte_record(record.clone()) {
+                    inference.add(&record);
+                }
+            }
+        }
+        let mut schema = inference.schema();
+        for (column, column_type) in header_types {
+            let column = schema.column_mut(&column);
+            column.column_type = column_type;
+            // The examples were written as the inferred type
+            let examples = mem::take(&mut column.examples);
+            column.examples = examples
+                .into_iter()
+                .map(|example| {
+                    let text = match example {
+                        Value::String(text) => text,
+                        example => example.to_string(),
+                    };
+                    data::string_to_value(&text, column, &self.options.types)
+                        .unwrap_or(Value::String(text))
+                })
+                .collect();
+        }
+        Ok(Schema {
+            dimensional_separator: self.options.dimensional_separator.clone(),
+            ..schema
+        })
+    }
+
+    fn csv_reader<R: Read>(&self, reader: R) -> csv::Reader<R> {
+        self.options
+            .dialect
+            .reader()
+            .has_headers(self.options.has_headers)
+            .from_reader(reader)
+    }
+
+    // The headers of the csv, without the types given in them when using typed headers
+    fn read_headers<R: Read>(
+        &self,
+        csv_reader: &mut csv::Reader<R>,
+    ) -> Result<(csv::StringRecord, Vec<(String, ColumnType)>)> {
+        let headers = csv_reader.headers()?;
+        if !self.options.has_headers {
+            // The first row is still read as a row
+            let names = (1..=headers.len()).map(|n| format!("column{}", n));
+            return Ok((names.collect(), vec![]));
+        }
+        if !self.options.typed_headers {
+            return Ok((headers.clone(), vec![]));
+        }
+        let mut names = csv::StringRecord::new();
+        let mut header_types = vec![];
+        for header in headers {
+            let (name, column_type) = types::split_typed_header(header);
+            names.push_field(name);
+            if let Some(column_type) = column_type {
+                header_types.push((name.to_owned(), column_type));
+            }
+        }
+        Ok((names, header_types))
+    }
+
+    /// Convert the csv one row at a time, keeping hold of the raw values for each
+    ///
+    /// Only the current row is held in memory. Folding does not apply here since it combines all
+    /// of the rows.
+    pub fn rows<R: Read>(&self, reader: R) -> Result<Rows<'_, R>> {
+        // Ragged rows are checked against the headers as each row is converted
+        let mut csv_reader = self.csv_reader(reader);
+        let (headers, header_types) = self.read_headers(&mut csv_reader)?;
+        self.options.schema.check_headers(&headers)?;
+        let mut warnings = vec![];
+
+        // Types given in the headers or on their own override the schema
+        let mut schema = self.options.schema.clone();
+        for (column, column_type) in header_types {
+            schema.column_mut(&column).column_type = column_type;
+        }
+        let typed = [
+            (&self.options.numeric_columns, ColumnType::Number),
+            (&self.options.boolean_columns, ColumnType::Boolean),
+            (&self.options.integer_columns, ColumnType::Integer),
+            (&self.options.float_columns, ColumnType::Float),
+            (&self.options.decimal_columns, ColumnType::Decimal),
+        ];
+        for (selectors, column_type) in typed.iter() {
+            for column in selector::select(selectors.iter(), &headers, &mut warnings) {
+                schema.column_mut(column).column_type = *column_type;
+            }
+        }
+        let dates = self
+            .options
+            .date_columns
+            .iter()
+            .map(|c| (c, ColumnType::Date));
+        let datetimes = self
+            .options
+            .datetime_columns
+            .iter()
+            .map(|c| (c, ColumnType::Datetime));
+        for ((selector, formats), column_type) in dates.chain(datetimes) {
+            for column in selector::select(Some(selector), &headers, &mut warnings) {
+                let column = schema.column_mut(column);
+                column.column_type = column_type;
+                if !formats.is_empty() {
+                    column.formats = formats.clone();
+                }
+            }
+        }
+
+        let validator = match self.options.validate {
+            Some(ref json_schema) if !self.options.fold => Some(
+                jsonschema::draft202012::new(&json_schema::row_schema(json_schema))
+                    .map_err(|err| Error::Schema(format!("Invalid JSON Schema: {}", err)))?,
+            ),
+            Some(ref json_schema) => Some(
+                jsonschema::draft202012::new(json_schema)
+                    .map_err(|err| Error::Schema(format!("Invalid JSON Schema: {}", err)))?,
+            ),
+            None => None,
+        };
+
+        let mut rows = Rows {
+            options: &self.options,
+            headers,
+            reader: csv_reader,
+            record: csv::ByteRecord::new(),
+            sample: VecDeque::new(),
+            schema,
+            empty_values: vec![],
+            paths: HashMap::new(),
+            rejects: None,
+            warnings,
+            on_warning: None,
+            warned_objects: HashSet::new(),
+            validator,
+            converted: 0,
+            rejected: 0,
+        };
+        if self.options.infer_types {
+            rows.infer_types()?;
+        }
+        rows.prepare_schema();
+        Ok(rows)
+    }
+
+    /// Convert the csv and write it out, either as pretty printed json or as jsonl
+    ///
+    /// Rows are written as soon as they are converted so memory use does not grow with the size
+    /// of the csv, unless folding, which has to hold on to every column until the end. The
+    /// writer is buffered, so it does not need to be a `BufWriter`.
+    pub fn write<R: Read, W: Write>(&self, reader: R, writer: W) -> Result<Summary> {
+        self.write_rows(self.rows(reader)?, writer)
+    }
+
+    /// Write rows already started with [`Converter::rows`], see [`Converter::write`]
+    ///
+    /// This is useful when the rows need to be set up first, for example with
+    /// [`Rows::rejects`].
+    ///
+    /// When a row fails with [`ErrorPolicy::Fail`] the rows before it have already been written.
+    /// A pretty printed array is left without its closing bracket so it can't be mistaken for
+    /// the whole output, but jsonl stops after the last row written, so check the result before
+    /// using it.
+    pub fn write_rows<R: Read, W: Write>(&self, mut rows: Rows<R>, writer: W) -> Result<Summary> {
+        let mut writer = BufWriter::new(writer);
+        if self.options.fold {
+            let folded = self.fold(&mut rows)?;
+            serde_json::to_writer_pretty(&mut writer, &folded)?;
+            writer.write_all(b"\n")?;
+        } else if self.options.jsonl {
+            for row in &mut rows {
+                serde_json::to_writer(&mut writer, &row?.value)?;
+                writer.write_all(b"\n")?;
+            }
+        } else {
+            // Serialize the array an element at a time, the output is the same as pretty printing
+            // the whole array at once.
+            {
+                let mut serializer = serde_json::Serializer::pretty(&mut writer);
+                let mut seq = serializer.serialize_seq(None)?;
+                for row in &mut rows {
+                    seq.serialize_element(&row?.value)?;
+                }
+                seq.end()?;
+            }
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        Ok(rows.summary())
+    }
+
+    /// Convert json back into csv, the reverse of [`Converter::write`]
+    ///
+    /// The input can be a json array of objects, one object per line (jsonl) or a folded object
+    /// with an array for each column, see [`ConvertOptions::fold`]. Nested objects and arrays are
+    /// flattened into headers joined by the dimensional separator, with the index of each array
+    /// item as its key. The headers are every key found in any of the objects, in the order they
+    /// are first seen.
+    pub fn write_csv<R: Read, W: Write>(&self, reader: R, writer: W) -> Result<Summary> {
+        let mut values = serde_json::Deserializer::from_reader(reader)
+            .into_iter::<Value>()
+            .collect::<::std::result::Result<Vec<Value>, _>>()
+            .map_err(Error::JsonInput)?;
+        let objects = if values.len() == 1 {
+            match values.pop().unwrap() {
+                Value::Array(items) => items,
+                value if self.options.fold || data::is_folded(&value) => data::unfold(value),
+                value => vec![value],
+            }
+        } else {
+            values
+        };
+
+        let ds = self.options.dimensional_separator.as_deref();
+        let mut headers: Vec<String> = Vec::new();
+        let mut header_indices: HashMap<String, usize> = HashMap::new();
+        let mut records = Vec::with_capacity(objects.len());
+        for (index, object) in objects.iter().enumerate() {
+            if !object.is_object() {
+                return Err(Error::JsonInput(serde::de::Error::custom(format!(
+                    "item {} is not an object",
+                    index + 1
+                ))));
+            }
+            let fields = data::flatten(object, ds);
+            // Such as {"a.b": 1, "a": {"b": 2}}, where one value would overwrite the other
+            let mut seen = HashSet::new();
+            for (header, _) in &fields {
+                if !seen.insert(header) {
+                    return Err(Error::JsonInput(serde::de::Error::custom(format!(
+                        "item {} has more than one value for the header {:?}",
+                        index + 1,
+                        header
+                    ))));
+                }
+                if !header_indices.contains_key(header) {
+                    header_indices.insert(header.clone(), headers.len());
+                    headers.push(header.clone());
+                }
+            }
+            records.push(fields);
+        }
+
+        let mut csv_writer = self
+            .options
+            .dialect
+            .writer()
+            .from_writer(BufWriter::new(writer));
+        // Without any keys there is nothing to write, not even an empty header row
+        if !headers.is_empty() {
+            csv_writer.write_record(&headers)?;
+        }
+        for fields in &records {
+            let mut record = vec![""; headers.len()];
+            for (header, value) in fields {
+                record[header_indices[header]] = value;
+            }
+            csv_writer.write_record(&record)?;
+        }
+        csv_writer.flush()?;
+        Ok(Summary {
+            converted: records.len() as u64,
+            rejected: 0,
+        })
+    }
+
+    fn fold<R: Read>(&self, rows: &mut Rows<R>) -> Result<Value> {
+        let ds = self.options.dimensional_separator.as_deref();
+        let mut folder = data::Folder::new(&rows.paths(), ds);
+        // The records of the folded rows, so rows that fail validation can be rejected
+        let mut records = vec![];
+        while let Some(row) = rows.next() {
+            folder.push(row?.value);
+            if rows.validator.is_some() {
+                records.push(rows.record.clone());
+            }
+        }
+        let mut folded = folder.finish();
+        if self.options.sort_keys {
+            folded = data::sort_keys(folded);
+        }
+        rows.validate_folded(&mut folded, records)?;
+        Ok(folded)
+    }
+}
+
+/// How many rows were converted and how many were rejected
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub converted: u64,
+    pub rejected: u64,
+}
+
+type OnWarning<'a> = Box<dyn FnMut(&Warning) + 'a>;
+
+/// An iterator converting one csv record at a time, see [`Converter::rows`]
+pub struct Rows<'a, R> {
+    options: &'a ConvertOptions,
+    headers: csv::StringRecord,
+    reader: csv::Reader<R>,
+    record: csv::ByteRecord,
+    // Records read ahead to infer the column types, these are converted before reading any more
+    sample: VecDeque<csv::ByteRecord>,
+    schema: Schema,
+    // The parts of the schema needed for each row
+    empty_values: Vec<(String, Value)>,
+    paths: HashMap<String, String>,
+    rejects: Option<csv::Writer<Box<dyn Write + 'a>>>,
+    // Warnings from setting up the rows, until there is somewhere for them to go
+    warnings: Vec<Warning>,
+    on_warning: Option<OnWarning<'a>>,
+    warned_objects: HashSet<String>,
+    validator: Option<jsonschema::Validator>,
+    converted: u64,
+    rejected: u64,
+}
+
+impl<'a, R: Read> Rows<'a, R> {
+    /// The header row of the csv
+    pub fn headers(&self) -> &csv::StringRecord {
+        &self.headers
+    }
+
+    /// The schema the rows are converted with
+    ///
+    /// This combines the schema and types given in the options with any inferred types.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// A JSON Schema (draft 2020-12) describing the json these rows are written as
+    ///
+    /// This follows the same nesting, arrays and types as the conversion, and describes the
+    /// folded object or a single jsonl document when those options are set.
+    pub fn json_schema(&self) -> Value {
+        // Put the index of each column where its value would go, then describe the result
+        let template = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(index, header)| (header.to_owned(), json!(index)))
+            .collect();
+        let mut template = self.nest(&template);
+        if self.options.arrays {
+            template = data::group_numeric_arrays(template, self.options.array_mode);
+        }
+        if self.options.fold {
+            let ds = self.options.dimensional_separator.as_deref();
+            let mut folder = data::Folder::new(&self.paths(), ds);
+            folder.push(template);
+            template = folder.finish();
+        }
+        if self.options.sort_keys {
+            template = data::sort_keys(template);
+        }
+
+        let columns: Vec<Value> = self
+            .headers
+            .iter()
+            .map(|header| json_schema::column(self.schema.column(header), &self.options.types))
+            .collect();
+        let optional = self.options.remove_empty_strings
+            || self.options.remove_empty_objects
+            || self.options.remove_nulls;
+        let schema = if self.options.fold {
+            json_schema::describe_folded(&template, &columns, optional)
+        } else {
+            let row = json_schema::describe(&template, &columns, optional);
+            if self.options.jsonl {
+                row
+            } else {
+                json!({"type": "array", "items": row})
+            }
+        };
+        json_schema::add_dialect(schema)
+    }
+
+    fn infer_types(&mut self) -> Result<()> {
+        let mut inference = TypeInference::new(&self.headers, &self.options.types);
+        while self.options.infer_sample == 0 || self.sample.len() < self.options.infer_sample {
+            let mut record = csv::ByteRecord::new();
+            if !self.reader.read_byte_record(&mut record)? {
+                break;
+            }
+            // Malformed records are left to be rejected when they are converted
+            if record.len() == self.headers.len() {
+                if let Ok(record) = csv::StringRecord::from_byte_record(record.clone()) {
+                    inference.add(&record);
+                }
+            }
+            self.sample.push_back(record);
+        }
+        // Only columns that have not been given a type are inferred
+        for (column, column_type) in inference.column_types() {
+            if self.schema.column(&column).is_none() {
+                self.schema.columns.push(ColumnSchema {
+                    nullable: true,
+                    ..ColumnSchema::new(column, column_type)
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn prepare_schema(&mut self) {
+        // After inferring types, so that these columns are still inferred
+        let nullable: Vec<&str> = if self.options.empty_as_null {
+            self.headers.iter().collect()
+        } else {
+            selector::select(
+                &self.options.nullable_columns,
+                &self.headers,
+                &mut self.warnings,
+            )
+        };
+        for column in nullable {
+            self.schema.column_mut(column).nullable = true;
+        }
+        self.empty_values = self
+            .schema
+            .columns
+            .iter()
+            .filter_map(|column| Some((column.name.clone(), column.empty_value()?)))
+            .collect();
+        self.paths = self
+            .schema
+            .columns
+            .iter()
+            .filter_map(|column| Some((column.name.clone(), column.path.clone()?)))
+            .collect();
+    }
+
+    // Where each column is put, its path in the schema or otherwise its header
+    fn paths(&self) -> csv::StringRecord {
+        self.headers
+            .iter()
+            .map(|header| self.paths.get(header).map_or(header, String::as_str))
+            .collect()
+    }
+
+    fn read_record(&mut self) -> csv::Result<bool> {
+        match self.sample.pop_front() {
+            Some(record) => {
+                self.record = record;
+                Ok(true)
+            }
+            None => self.reader.read_byte_record(&mut self.record),
+        }
+    }
+
+    /// Also write every rejected record to `writer` as csv
+    ///
+    /// The original headers are written first, followed by `rejected_line` and `rejected_reason`
+    /// columns. Each record keeps its original fields, short records are padded out to the
+    /// number of headers so the extra columns line up, and the fields of long records that have
+    /// no header come after `rejected_reason`.
+    pub fn rejects<W: Write + 'a>(mut self, writer: W) -> Result<Self> {
+        let mut rejects = self
+            .options
+            .dialect
+            .writer()
+            .from_writer(Box::new(writer) as Box<dyn Write + 'a>);
+        // The headers as they were written, with any types
+        let mut headers = if self.options.has_headers {
+            self.reader.headers()?.clone()
+        } else {
+            self.headers.clone()
+        };
+        headers.push_field("rejected_line");
+        headers.push_field("rejected_reason");
+        rejects.write_record(&headers)?;
+        self.rejects = Some(rejects);
+        Ok(self)
+    }
+
+    /// Pass each [`Warning`] to `on_warning` as it happens, starting with any from setting up the
+    /// rows, otherwise they are ignored
+    pub fn on_warning<F: FnMut(&Warning) + 'a>(mut self, mut on_warning: F) -> Self {
+        for warning in self.warnings.drain(..) {
+            on_warning(&warning);
+        }
+        self.on_warning = Some(Box::new(on_warning));
+        self
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        if let Some(ref mut on_warning) = self.on_warning {
+            on_warning(&warning);
+        }
+    }
+
+    /// How many rows have been converted and rejected so far
+    pub fn summary(&self) -> Summary {
+        Summary {
+            converted: self.converted,
+            rejected: self.rejected,
+        }
+    }
+
+    fn parse(&self) -> ::std::result::Result<csv::StringRecord, String> {
+        let mut record = self.record.clone();
+        if record.len() != self.headers.len() {
+            if !self.options.flexible {
+                return Err(format!(
+                    "found {} fields but there are {} headers",
+                    record.len(),
+                    self.headers.len()
+                ));
+            }
+            record.truncate(self.headers.len());
+            while record.len() < self.headers.len() {
+                record.push_field(b"");
+            }
+        }
+        csv::StringRecord::from_byte_record(record).map_err(|err| err.utf8_error().to_string())
+    }
+
+    fn reject(&mut self, reason: String) -> Result<()> {
+        let record = mem::take(&mut self.record);
+        let result = self.reject_record(&record, reason);
+        self.record = record;
+        result
+    }
+
+    fn reject_record(&mut self, record: &csv::ByteRecord, reason: String) -> Result<()> {
+        self.rejected += 1;
+        let position = record
+            .position()
+            .cloned()
+            .unwrap_or_else(csv::Position::new);
+        let rejection = Rejection {
+            line: position.line(),
+            byte: position.byte(),
+            reason,
+        };
+        if let Some(ref mut rejects) = self.rejects {
+            let headers = self.headers.len();
+            let mut rejected: csv::ByteRecord = record.iter().take(headers).collect();
+            while rejected.len() < headers {
+                rejected.push_field(b"");
+            }
+            rejected.push_field(rejection.line.to_string().as_bytes());
+            rejected.push_field(rejection.reason.as_bytes());
+            for field in record.iter().skip(headers) {
+                rejected.push_field(field);
+            }
+            rejects.write_byte_record(&rejected)?;
+        }
+        match self.options.on_error {
+            ErrorPolicy::Skip => Ok(()),
+            ErrorPolicy::Warn => {
+                self.warn(Warning::Skipped(rejection));
+                Ok(())
+            }
+            ErrorPolicy::Fail => {
+                self.flush_rejects()?;
+                Err(Error::Rejected(rejection))
+            }
+        }
+    }
+
+    fn flush_rejects(&mut self) -> Result<()> {
+        if let Some(ref mut rejects) = self.rejects {
+            rejects.flush()?;
+        }
+        Ok(())
+    }
+
+    // Reject the folded rows that do not match the JSON Schema and take them out of the arrays.
+    // Each error points into the array of a column, at the index of the row.
+    fn validate_folded(&mut self, folded: &mut Value, records: Vec<csv::ByteRecord>) -> Result<()> {
+        let errors = match self.validator {
+            Some(ref validator) => validation_errors(validator, folded),
+            None => return Ok(()),
+        };
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let mut reasons: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        for (pointer, reason) in errors {
+            match pointer
+                .split('/')
+                .nth(2)
+                .and_then(|index| index.parse().ok())
+            {
+                Some(index) if index < records.len() => {
+                    reasons.entry(index).or_default().push(reason)
+                }
+                _ => {
+                    return Err(Error::Schema(format!(
+                        "The folded json does not match the JSON Schema: {}",
+                        reason
+                    )))
+                }
+            }
+        }
+        for (index, reasons) in &reasons {
+            self.reject_record(&records[*index], reasons.join("; "))?;
+            self.converted -= 1;
+        }
+        if let Value::Object(columns) = folded {
+            for column in columns.values_mut() {
+                if let Value::Array(items) = column {
+                    reasons.keys().rev().for_each(|index| {
+                        items.remove(*index);
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Put each column at its path, splitting on the dimensional separator
+    fn nest(&self, row: &Map<String, Value>) -> Value {
+        let ds = self.options.dimensional_separator.as_deref();
+        let mut items = Map::new();
+        row.iter().for_each(|(key, value)| {
+            let key = self.paths.get(key).unwrap_or(key);
+            let (key, value) = data::dimensional_converter(key, value, ds);
+            let prepared_value = data::prepare_upsert(items.entry(key.clone()), value);
+            items.insert(key, prepared_value);
+        });
+        json!(items)
+    }
+
+    fn convert(&mut self, record: &csv::StringRecord) -> ::std::result::Result<Value, String> {
+        let row = data::row_to_values(&self.headers, record);
+        let row = data::replace_null_values(&self.options.types.null_values, row);
+        let row = data::fill_empty_values(&self.empty_values, row);
+        let row = data::columns_to_types(&self.schema.columns, &self.options.types, row)?;
+
+        let mut item = self.nest(&row);
+        if self.options.arrays {
+            // Only warn about each object once rather than for every row
+            for pointer in data::mixed_numeric_keys(&item) {
+                if self.warned_objects.insert(pointer.clone()) {
+                    self.warn(Warning::MixedKeys(pointer));
+                }
+            }
+            if self.options.array_mode == ArrayMode::Positional {
+                for pointer in data::positions_out_of_range(&item) {
+                    if self.warned_objects.insert(pointer.clone()) {
+                        self.warn(Warning::PositionsOutOfRange(pointer));
+                    }
+                }
+            }
+            item = data::group_numeric_arrays(item, self.options.array_mode);
+        }
+        if self.options.remove_nulls {
+            item = data::remove_nulls(item);
+        }
+        if self.options.remove_empty_strings {
+            item = data::remove_empty_strings(item);
+        }
+        if self.options.remove_empty_objects {
+            item = data::remove_empty_objects(item);
+        }
+        if self.options.sort_keys {
+            item = data::sort_keys(item);
+        }
+        // The folded document is validated as a whole instead
+        if let (Some(validator), false) = (&self.validator, self.options.fold) {
+            let errors = validation_errors(validator, &item);
+            if !errors.is_empty() {
+                let reasons: Vec<String> = errors.into_iter().map(|(_, reason)| reason).collect();
+                return Err(reasons.join("; "));
+            }
+        }
+        Ok(item)
+    }
+}
+
+// The JSON Pointer of each value that does not match the JSON Schema, and why
+fn validation_errors(validator: &jsonschema::Validator, value: &Value) -> Vec<(String, String)> {
+    validator
+        .iter_errors(value)
+        .map(|err| {
+            let pointer = err.instance_path.as_str().to_owned();
+            let reason = format!("{} at {:?}", err, pointer);
+            (pointer, reason)
+        })
+        .collect()
+}
+
+impl<'a, R: Read> Iterator for Rows<'a, R> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.read_record() {
+                Ok(true) => {}
+                Ok(false) => return self.flush_rejects().err().map(Err),
+                Err(err) => return Some(Err(err.into())),
+            }
+            if self.record.is_empty() {
+                continue;
+            }
+            let converted = self.parse().and_then(|record| {
+                let value = self.convert(&record)?;
+                let raw = self
+                    .headers
+                    .iter()
+                    .zip(record.iter())
+                    .map(|(header, value)| (header.to_owned(), value.to_owned()))
+                    .collect();
+                Ok((raw, value))
+            });
+            let (raw, value) = match converted {
+                Ok(converted) => converted,
+                Err(reason) => match self.reject(reason) {
+                    Ok(()) => continue,
+                    Err(err) => return Some(Err(err)),
+                },
+            };
+            // Rows left empty are removed along with any other empty objects
+            if self.options.remove_empty_objects && value.as_object().is_some_and(Map::is_empty) {
+                continue;
+            }
+            self.converted += 1;
+            return Some(Ok(Row { raw, value }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_with_default_options() {
+        let converter = ConvertOptions::new().build();
+        let items = converter.convert("foo,bar\n1,a\n2,b\n".as_bytes()).unwrap();
+        assert_eq!(
+            items,
+            json!([{"foo": "1", "bar": "a"}, {"foo": "2", "bar": "b"}])
+        );
+    }
+
+    #[test]
+    fn it_applies_every_option() {
+        let converter = ConvertOptions::new()
+            .delimiter(b';')
+            .dimensional_separator(".")
+            .arrays(true)
+            .numeric_column("age")
+            .boolean_column("active")
+            .remove_empty_strings(true)
+            .build();
+        let items = converter
+            .convert("name;age;active;pets.1;pets.2\ndaniel;34;0;yuki;\n".as_bytes())
+            .unwrap();
+        assert_eq!(
+            items,
+            json!([{"name": "daniel", "age": 34, "active": false, "pets": ["yuki"]}])
+        );
+    }
+
+    #[test]
+    fn it_keeps_the_order_of_the_columns() {
+        let csv = "b.z,a,b.y,c\n1,2,3,4\n";
+        let converter = ConvertOptions::new().dimensional_separator(".").build();
+        let items = converter.convert(csv.as_bytes()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&items).unwrap(),
+            r#"[{"b":{"z":"1","y":"3"},"a":"2","c":"4"}]"#
+        );
+
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .fold(true)
+            .build();
+        let items = converter.convert(csv.as_bytes()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&items).unwrap(),
+            r#"{"b":[{"z":"1","y":"3"}],"a":["2"],"c":["4"]}"#
+        );
+
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .sort_keys(true)
+            .build();
+        let items = converter.convert(csv.as_bytes()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&items).unwrap(),
+            r#"[{"a":"2","b":{"y":"3","z":"1"},"c":"4"}]"#
+        );
+    }
+
+    #[test]
+    fn it_infers_types() {
+        let csv = "id,price,active,notes,name\n1,1.5,true,,daniel\n2,,false,,3\n";
+        let converter = ConvertOptions::new().infer_types(true).build();
+        let items = converter.convert(csv.as_bytes()).unwrap();
+        assert_eq!(
+            items,
+            json!([
+                {"id": 1, "price": 1.5, "active": true, "notes": null, "name": "daniel"},
+                {"id": 2, "price": null, "active": false, "notes": null, "name": "3"}
+            ])
+        );
+    }
+
+    #[test]
+    fn it_only_infers_types_from_the_sample() {
+        let csv = "id\n1\n2\nthree\n";
+        let converter = ConvertOptions::new()
+            .infer_types(true)
+            .infer_sample(2)
+            .on_error(ErrorPolicy::Skip)
+            .build();
+        let mut rows = converter.rows(csv.as_bytes()).unwrap();
+        assert_eq!(
+            rows.schema().columns,
+            vec![ColumnSchema {
+                nullable: true,
+                ..ColumnSchema::new("id", ColumnType::Integer)
+            }]
+        );
+        let values: Vec<Value> = rows.by_ref().map(|row| row.unwrap().value).collect();
+        assert_eq!(values, vec![json!({"id": 1}), json!({"id": 2})]);
+        assert_eq!(rows.summary().rejected, 1);
+    }
+
+    #[test]
+    fn it_converts_with_a_schema() {
+        let schema = Schema::from_json(
+            r#"{"columns": [
+                {"name": "age", "type": "integer", "default": -1},
+                {"name": "first", "path": "name.first"},
+                {"name": "last", "path": "name.last", "nullable": true},
+                {"name": "score", "type": "float", "nullable": true}
+            ]}"#,
+        )
+        .unwrap();
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .schema(schema)
+            .boolean_column("score")
+            .build();
+        let items = converter
+            .convert("first,last,age,score\ndaniel,,,1\n".as_bytes())
+            .unwrap();
+        assert_eq!(
+            items,
+            json!([{"name": {"first": "daniel", "last": null}, "age": -1, "score": true}])
+        );
+        let schema =
+            Schema::from_json(r#"{"columns": [{"name": "first", "path": "name.first"}]}"#).unwrap();
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .schema(schema)
+            .fold(true)
+            .build();
+        let folded = converter
+            .convert(
+                "first,age
+daniel,34
+ann,27
+"
+                .as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(
+            folded,
+            json!({"name": [{"first": "daniel"}, {"first": "ann"}], "age": ["34", "27"]})
+        );
+    }
+
+    #[test]
+    fn it_infers_a_schema_it_can_convert_with() {
+        let csv = "id,name.first,score\n1,daniel,1.5\n2,,2\n";
+        let schema = ConvertOptions::new()
+            .dimensional_separator(".")
+            .build()
+            .infer_schema(csv.as_bytes())
+            .unwrap();
+        assert_eq!(schema.dimensional_separator, Some(String::from(".")));
+        let types: Vec<ColumnType> = schema.columns.iter().map(|c| c.column_type).collect();
+        assert_eq!(
+            types,
+            vec![ColumnType::Integer, ColumnType::String, ColumnType::Float]
+        );
+        assert!(schema.columns[1].nullable);
+
+        let converter = ConvertOptions::new().schema(schema).build();
+        assert_eq!(
+            converter.convert(csv.as_bytes()).unwrap(),
+            json!([
+                {"id": 1, "name": {"first": "daniel"}, "score": 1.5},
+                {"id": 2, "name": {"first": null}, "score": 2.0}
+            ])
+        );
+    }
+
+    #[test]
+    fn it_describes_the_output_as_a_json_schema() {
+        let schema =
+            Schema::from_json(r#"{"columns": [{"name": "age", "nullable": true}]}"#).unwrap();
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .arrays(true)
+            .schema(schema)
+            .numeric_column("age")
+            .boolean_column("tags.1")
+            .build();
+        let csv = "name.first,age,tags.0,tags.1\n";
+        let rows = converter.rows(csv.as_bytes()).unwrap();
+        assert_eq!(
+            rows.json_schema(),
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": {
+                            "type": "object",
+                            "properties": {"first": {"type": "string"}},
+                            "additionalProperties": false,
+                            "required": ["first"]
+                        },
+                        "age": {"type": ["number", "null"]},
+                        "tags": {
+                            "type": "array",
+                            "items": {"anyOf": [{"type": "string"}, {"type": "boolean"}]}
+                        }
+                    },
+                    "additionalProperties": false,
+                    "required": ["name", "age", "tags"]
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn it_describes_folded_output_as_a_json_schema() {
+        let converter = ConvertOptions::new()
+            .fold(true)
+            .remove_empty_strings(true)
+            .build();
+        let rows = converter.rows("a\n".as_bytes()).unwrap();
+        assert_eq!(
+            rows.json_schema(),
+            json!({
+                "$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "object",
+                "properties": {
+                    "a": {
+                        "type": "array",
+                        "items": {"anyOf": [{"type": "string"}, {"type": "null"}]}
+                    }
+                },
+                "required": ["a"],
+                "additionalProperties": false
+            })
+        );
+    }
+
+    #[test]
+    fn it_rejects_rows_that_do_not_match_the_json_schema() {
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .validate(json!({"properties": {"name": {"properties": {"first": {"maxLength": 5}}}}}))
+            .on_error(ErrorPolicy::Fail)
+            .build();
+        match converter.convert("name.first,age\nalex,34\ndaniel,20\n".as_bytes()) {
+            Err(Error::Rejected(rejection)) => {
+                assert_eq!(rejection.line, 3);
+                assert_eq!(
+                    rejection.reason,
+                    r#""daniel" is longer than 5 characters at "/name/first""#
+                );
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_validates_each_row_against_the_items_of_an_array_schema() {
+        let csv = "name,age\nann,34\nbob,27\n";
+        let options = ConvertOptions::new()
+            .numeric_column("age")
+            .on_error(ErrorPolicy::Skip);
+        let json_schema = {
+            let converter = options.clone().build();
+            let rows = converter.rows(csv.as_bytes()).unwrap();
+            rows.json_schema()
+        };
+        assert_eq!(json_schema["type"], "array");
+
+        // The schema written for the output matches each row of it
+        let converter = options.validate(json_schema.clone()).build();
+        let json = converter.convert(csv.as_bytes()).unwrap();
+        assert_eq!(
+            json,
+            json!([{"name": "ann", "age": 34}, {"name": "bob", "age": 27}])
+        );
+
+        // Without the numeric column the ages are strings
+        let converter = ConvertOptions::new()
+            .validate(json_schema)
+            .on_error(ErrorPolicy::Skip)
+            .build();
+        let json = converter.convert(csv.as_bytes()).unwrap();
+        assert_eq!(json, json!([]));
+    }
+
+    #[test]
+    fn it_drops_folded_rows_that_do_not_match_the_json_schema() {
+        let converter = ConvertOptions::new()
+            .fold(true)
+            .validate(json!({"properties": {"name": {"items": {"maxLength": 5}}}}))
+            .on_error(ErrorPolicy::Skip)
+            .build();
+        let mut rejects = vec![];
+        let mut output = vec![];
+        let summary = {
+            let rows = converter
+                .rows("name,age\nalex,34\ndaniel,20\nsam,18\n".as_bytes())
+                .unwrap()
+                .rejects(&mut rejects)
+                .unwrap();
+            converter.write_rows(rows, &mut output).unwrap()
+        };
+        assert_eq!(
+            serde_json::from_slice::<Value>(&output).unwrap(),
+            json!({"name": ["alex", "sam"], "age": ["34", "18"]})
+        );
+        assert_eq!(
+            summary,
+            Summary {
+                converted: 2,
+                rejected: 1
+            }
+        );
+        assert_eq!(
+            String::from_utf8(rejects).unwrap(),
+            "name,age,rejected_line,rejected_reason\n\
+             daniel,20,3,\"\"\"daniel\"\" is longer than 5 characters at \"\"/name/1\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn it_fails_on_an_invalid_json_schema() {
+        let converter = ConvertOptions::new()
+            .validate(json!({"type": "nonsense"}))
+            .build();
+        match converter.convert("a\n1\n".as_bytes()) {
+            Err(Error::Schema(_)) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_converts_json_back_into_csv() {
+        let converter = ConvertOptions::new().dimensional_separator(".").build();
+        let csv = "name.first,age,tags.0,tags.1\ndaniel,34,a,b\nalex,20,c,\n";
+        let mut json = vec![];
+        converter.write(csv.as_bytes(), &mut json).unwrap();
+        let mut output = vec![];
+        converter.write_csv(json.as_slice(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), csv);
+    }
+
+    #[test]
+    fn it_converts_jsonl_and_folded_json_into_csv_with_every_key() {
+        let converter = ConvertOptions::new().build();
+        let mut jsonl = vec![];
+        let summary = converter
+            .write_csv(
+                "{\"a\": 1}\n{\"b\": true, \"a\": null}\n".as_bytes(),
+                &mut jsonl,
+            )
+            .unwrap();
+        assert_eq!(summary.converted, 2);
+        assert_eq!(String::from_utf8(jsonl).unwrap(), "a,b\n1,\n,true\n");
+
+        let mut folded = vec![];
+        converter
+            .write_csv(r#"{"a": [1, 2], "b": ["x", "y"]}"#.as_bytes(), &mut folded)
+            .unwrap();
+        assert_eq!(String::from_utf8(folded).unwrap(), "a,b\n1,x\n2,y\n");
+    }
+
+    #[test]
+    fn it_only_unfolds_a_single_column_when_told_the_json_is_folded() {
+        let json = r#"{"tags": ["a", "b"]}"#;
+        let mut record = vec![];
+        ConvertOptions::new()
+            .dimensional_separator(".")
+            .build()
+            .write_csv(json.as_bytes(), &mut record)
+            .unwrap();
+        assert_eq!(String::from_utf8(record).unwrap(), "tags.0,tags.1\na,b\n");
+
+        let mut folded = vec![];
+        ConvertOptions::new()
+            .fold(true)
+            .build()
+            .write_csv(json.as_bytes(), &mut folded)
+            .unwrap();
+        assert_eq!(String::from_utf8(folded).unwrap(), "tags\na\nb\n");
+    }
+
+    #[test]
+    fn it_fails_to_convert_json_with_two_values_for_a_header() {
+        let converter = ConvertOptions::new().dimensional_separator(".").build();
+        match converter.write_csv(r#"[{"a.b": 1, "a": {"b": 2}}]"#.as_bytes(), vec![]) {
+            Err(Error::JsonInput(err)) => assert_eq!(
+                err.to_string(),
+                r#"item 1 has more than one value for the header "a.b""#
+            ),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_writes_nothing_for_an_empty_array() {
+        let mut csv = vec![];
+        let summary = ConvertOptions::new()
+            .build()
+            .write_csv("[]".as_bytes(), &mut csv)
+            .unwrap();
+        assert_eq!(summary.converted, 0);
+        assert!(csv.is_empty());
+    }
+
+    #[test]
+    fn it_fails_to_convert_json_that_is_not_objects() {
+        match ConvertOptions::new()
+            .build()
+            .write_csv("[1]".as_bytes(), vec![])
+        {
+            Err(Error::JsonInput(_)) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_converts_integers_and_floats_strictly() {
+        let converter = ConvertOptions::new()
+            .integer_columns(vec!["count"])
+            .float_columns(vec!["price"])
+            .on_type_error(TypeErrorPolicy::Null)
+            .build();
+        let csv = "count,price\n1,1\n1.5,1.5\n";
+        assert_eq!(
+            serde_json::to_string(&converter.convert(csv.as_bytes()).unwrap()).unwrap(),
+            r#"[{"count":1,"price":1.0},{"count":null,"price":1.5}]"#
+        );
+    }
+
+    #[test]
+    fn it_keeps_values_that_do_not_fit_their_type_if_asked() {
+        let schema = Schema::from_json(
+            r#"{"columns": [{"name": "count", "on_type_error": "default", "default": -1}]}"#,
+        )
+        .unwrap();
+        let converter = ConvertOptions::new()
+            .schema(schema)
+            .integer_columns(vec!["count"])
+            .float_columns(vec!["price"])
+            .on_type_error(TypeErrorPolicy::Keep)
+            .build();
+        let csv = "count,price\n1,1\nsome,free\n";
+        let rows = converter.rows(csv.as_bytes()).unwrap();
+        assert_eq!(
+            rows.json_schema()["items"]["properties"],
+            json!({
+                "count": {"type": "integer"},
+                "price": {"anyOf": [{"type": "number"}, {"type": "string"}]}
+            })
+        );
+        assert_eq!(
+            serde_json::to_string(&converter.convert(csv.as_bytes()).unwrap()).unwrap(),
+            r#"[{"count":1,"price":1.0},{"count":-1,"price":"free"}]"#
+        );
+    }
+
+    #[test]
+    fn it_converts_decimals_exactly() {
+        let converter = ConvertOptions::new()
+            .decimal_columns(vec!["amount"])
+            .jsonl(true)
+            .build();
+        let csv = "amount\n0.1\n12345678901234567.89\n+1.50\n";
+        let mut json = Vec::new();
+        converter.write(csv.as_bytes(), &mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"amount\":0.1}\n{\"amount\":12345678901234567.89}\n{\"amount\":1.50}\n"
+        );
+    }
+
+    #[test]
+    fn it_reads_numbers_in_the_number_format() {
+        let converter = ConvertOptions::new()
+            .delimiter(b';')
+            .numeric_columns(vec!["price"])
+            .decimal_columns(vec!["total"])
+            .float_columns(vec!["discount"])
+            .number_format(NumberFormat {
+                divide_percentages: true,
+                ..NumberFormat::from_locale("de").unwrap()
+            })
+            .build();
+        let csv = "price;total;discount\n1.234,5;(12,30 €);15 %\n";
+        assert_eq!(
+            serde_json::to_string(&converter.convert(csv.as_bytes()).unwrap()).unwrap(),
+            r#"[{"price":1234.5,"total":-12.30,"discount":0.15}]"#
+        );
+    }
+
+    #[test]
+    fn it_reads_booleans_strictly() {
+        let converter = ConvertOptions::new()
+            .boolean_columns(vec!["active"])
+            .boolean_format(BooleanFormat {
+                true_values: vec![String::from("ja")],
+                false_values: vec![String::from("nein")],
+                strict: true,
+                empty_is_null: true,
+            })
+            .on_type_error(TypeErrorPolicy::Null)
+            .build();
+        let csv = "id,active\n1,Ja\n2,nein\n3,\n4,yes\n";
+        let active: Vec<Value> = match converter.convert(csv.as_bytes()).unwrap() {
+            Value::Array(rows) => rows.iter().map(|row| row["active"].clone()).collect(),
+            json => panic!("Unexpected json: {}", json),
+        };
+        assert_eq!(
+            active,
+            vec![json!(true), json!(false), json!(null), json!(null)]
+        );
+    }
+
+    #[test]
+    fn it_makes_null_values_and_empty_values_null() {
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .numeric_columns(vec!["age"])
+            .null_values(vec!["N/A"])
+            .nullable_columns(vec!["age"])
+            .build();
+        let csv = "name.first,name.last,age\nN/A,,\nJames,N/A,N/A\n";
+        assert_eq!(
+            converter.convert(csv.as_bytes()).unwrap(),
+            json!([
+                {"name": {"first": null, "last": ""}, "age": null},
+                {"name": {"first": "James", "last": null}, "age": null}
+            ])
+        );
+
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .empty_as_null(true)
+            .remove_nulls(true)
+            .remove_empty_objects(true)
+            .infer_types(true)
+            .build();
+        let csv = "name.first,name.last,age\n,,34\nJames,,\n";
+        assert_eq!(
+            converter.convert(csv.as_bytes()).unwrap(),
+            json!([{"age": 34}, {"name": {"first": "James"}}])
+        );
+    }
+
+    #[test]
+    fn it_takes_types_from_the_headers() {
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .typed_headers(true)
+            .float_columns(vec!["score"])
+            .build();
+        let csv = "name.first,age:int,score:number,active:bool,tags:json\n\
+                   James,34,1,yes,\"[1,2]\"\n";
+        let mut rows = converter.rows(csv.as_bytes()).unwrap();
+        assert_eq!(
+            rows.headers(),
+            &csv::StringRecord::from(vec!["name.first", "age", "score", "active", "tags"])
+        );
+        assert_eq!(
+            serde_json::to_string(&rows.next().unwrap().unwrap().value).unwrap(),
+            r#"{"name":{"first":"James"},"age":34,"score":1.0,"active":true,"tags":[1,2]}"#
+        );
+    }
+
+    #[test]
+    fn it_selects_columns_with_globs_and_regular_expressions() {
+        let converter = ConvertOptions::new()
+            .numeric_column("scores.*")
+            .boolean_column(Selector::regex("^is_").unwrap())
+            .date_column("*_on", Vec::<String>::new())
+            .integer_columns(vec!["missing"])
+            .jsonl(true)
+            .build();
+        let csv = "scores.1,scores.2,is_active,this_is_not,joined_on\n1,2.5,yes,no,2021-04-03\n";
+        let rows = converter.rows(csv.as_bytes()).unwrap();
+        let types: Vec<ColumnType> = rows
+            .schema()
+            .columns
+            .iter()
+            .map(|column| column.column_type)
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                ColumnType::Number,
+                ColumnType::Number,
+                ColumnType::Boolean,
+                ColumnType::Date
+            ]
+        );
+    }
+
+    #[test]
+    fn it_converts_dates_and_datetimes() {
+        let converter = ConvertOptions::new()
+            .date_column("joined", vec!["%d/%m/%Y", "%Y-%m-%d"])
+            .datetime_column("seen", Vec::<String>::new())
+            .timezone(FixedOffset::east_opt(0).unwrap())
+            .build();
+        let csv = "joined,seen\n03/04/2021,2021-04-03T10:00\n2021-04-05,2021-04-05 11:30\n";
+        assert_eq!(
+            converter.convert(csv.as_bytes()).unwrap(),
+            json!([
+                {"joined": "2021-04-03", "seen": "2021-04-03T10:00:00Z"},
+                {"joined": "2021-04-05", "seen": "2021-04-05T11:30:00Z"}
+            ])
+        );
+    }
+
+    #[test]
+    fn it_fails_when_a_required_column_is_missing() {
+        let schema =
+            Schema::from_json(r#"{"columns": [{"name": "age", "required": true}]}"#).unwrap();
+        let converter = ConvertOptions::new().schema(schema).build();
+        match converter.convert("name\ndaniel\n".as_bytes()) {
+            Err(Error::Schema(_)) => {}
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn it_folds() {
+        let converter = ConvertOptions::new().fold(true).build();
+        let items = converter.convert("foo,bar\n1,a\n2,b\n".as_bytes()).unwrap();
+        assert_eq!(items, json!({"foo": ["1", "2"], "bar": ["a", "b"]}));
+    }
+
+    #[test]
+    fn it_writes_jsonl() {
+        let converter = ConvertOptions::new().jsonl(true).build();
+        let mut output = Vec::new();
+        converter
+            .write("foo\n1\n2\n".as_bytes(), &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"foo\":\"1\"}\n{\"foo\":\"2\"}\n"
+        );
+    }
+
+    #[test]
+    fn it_writes_the_same_json_as_pretty_printing_the_whole_array() {
+        let csv = "name.first,name.last,pets.1,pets.2\ndaniel,mason,yuki,tinky\njames,smith,,\n";
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .arrays(true)
+            .build();
+        let mut output = Vec::new();
+        converter.write(csv.as_bytes(), &mut output).unwrap();
+        let expected = serde_json::to_string_pretty(&converter.convert(csv.as_bytes()).unwrap());
+        assert_eq!(String::from_utf8(output).unwrap(), expected.unwrap() + "\n");
+
+        let mut output = Vec::new();
+        converter.write("foo\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn it_drops_rows_left_empty() {
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .remove_empty_strings(true)
+            .remove_empty_objects(true)
+            .build();
+        let items = converter.convert("a.b,c\n,\n1,\n".as_bytes()).unwrap();
+        assert_eq!(items, json!([{"a": {"b": "1"}}]));
+    }
+
+    #[test]
+    fn it_skips_and_counts_malformed_rows() {
+        let converter = ConvertOptions::new().on_error(ErrorPolicy::Skip).build();
+        let csv = b"foo,bar\n1,a\n2\n3,\xff\n4,d\n";
+        let mut output = Vec::new();
+        let summary = converter.write(&csv[..], &mut output).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                converted: 2,
+                rejected: 2
+            }
+        );
+        let items: Value = serde_json::from_slice(&output).unwrap();
+        assert_eq!(
+            items,
+            json!([{"foo": "1", "bar": "a"}, {"foo": "4", "bar": "d"}])
+        );
+    }
+
+    #[test]
+    fn it_fails_on_malformed_rows_with_their_position() {
+        let converter = ConvertOptions::new().on_error(ErrorPolicy::Fail).build();
+        let err = converter
+            .convert("foo,bar\n1,a\n2\n".as_bytes())
+            .unwrap_err();
+        match err {
+            Error::Rejected(rejection) => assert_eq!(
+                rejection,
+                Rejection {
+                    line: 3,
+                    byte: 12,
+                    reason: String::from("found 1 fields but there are 2 headers"),
+                }
+            ),
+            err => panic!("Unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn it_pads_and_truncates_ragged_rows_when_flexible() {
+        let converter = ConvertOptions::new()
+            .flexible(true)
+            .on_error(ErrorPolicy::Fail)
+            .build();
+        let json = converter.convert("foo,bar\n1\n2,b,c\n".as_bytes()).unwrap();
+        assert_eq!(
+            json,
+            json!([{"foo": "1", "bar": ""}, {"foo": "2", "bar": "b"}])
+        );
+    }
+
+    #[test]
+    fn it_names_the_columns_when_there_are_no_headers() {
+        let converter = ConvertOptions::new()
+            .has_headers(false)
+            .numeric_column("column2")
+            .on_error(ErrorPolicy::Skip)
+            .build();
+        let mut rejects = Vec::new();
+        let json = {
+            let rows = converter
+                .rows("tea,1\ncake,n/a\n".as_bytes())
+                .unwrap()
+                .rejects(&mut rejects)
+                .unwrap();
+            rows.map(|row| row.unwrap().value).collect::<Vec<Value>>()
+        };
+        assert_eq!(json, vec![json!({"column1": "tea", "column2": 1})]);
+        assert_eq!(
+            String::from_utf8(rejects).unwrap(),
+            "column1,column2,rejected_line,rejected_reason\n\
+             cake,n/a,2,\"column column2: \"\"n/a\"\" is not a number\"\n"
+        );
+    }
+
+    #[test]
+    fn it_reads_and_rejects_records_in_the_dialect() {
+        let converter = ConvertOptions::new()
+            .delimiter(b';')
+            .quote(b'\'')
+            .escape(b'\\')
+            .comment(b'#')
+            .trim(Trim::Fields)
+            .numeric_column("foo")
+            .on_error(ErrorPolicy::Skip)
+            .build();
+        let mut rejects = Vec::new();
+        let json = {
+            let rows = converter
+                .rows("# exported today\nfoo;bar\n 1 ;'a;b'\nn/a;'it\\'s'\n".as_bytes())
+                .unwrap()
+                .rejects(&mut rejects)
+                .unwrap();
+            rows.map(|row| row.unwrap().value).collect::<Vec<Value>>()
+        };
+        assert_eq!(json, vec![json!({"foo": 1, "bar": "a;b"})]);
+        assert_eq!(
+            String::from_utf8(rejects).unwrap(),
+            "foo;bar;rejected_line;rejected_reason\n\
+             n/a;'it\\'s';4;column foo: \"n/a\" is not a number\n"
+        );
+    }
+
+    #[test]
+    fn it_passes_on_warnings() {
+        let converter = ConvertOptions::new()
+            .dimensional_separator(".")
+            .arrays(true)
+            .numeric_column("age")
+            .build();
+        let mut warnings = vec![];
+        let converted = converter
+            .rows("id,tags.0,tags.x\n1,a,b\n2\n".as_bytes())
+            .unwrap()
+            .on_warning(|warning| warnings.push(warning.clone()))
+            .count();
+        assert_eq!(converted, 1);
+        assert_eq!(
+            warnings,
+            vec![
+                Warning::NoColumnsMatching(String::from("age")),
+                Warning::MixedKeys(String::from("/tags")),
+                Warning::Skipped(Rejection {
+                    line: 3,
+                    byte: 23,
+                    reason: String::from("found 1 fields but there are 3 headers"),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_writes_rejected_records() {
+        let converter = ConvertOptions::new()
+            .numeric_column("foo")
+            .on_error(ErrorPolicy::Skip)
+            .build();
+        let mut rejects = Vec::new();
+        {
+            let rows = converter
+                .rows("foo,bar\n1,a\n2\nn/a,c\n4,d,e\n".as_bytes())
+                .unwrap()
+                .rejects(&mut rejects)
+                .unwrap();
+            converter.write_rows(rows, Vec::new()).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(rejects).unwrap(),
+            "foo,bar,rejected_line,rejected_reason\n\
+             2,,3,found 1 fields but there are 2 headers\n\
+             n/a,c,4,\"column foo: \"\"n/a\"\" is not a number\"\n\
+             4,d,5,found 3 fields but there are 2 headers,e\n"
+        );
+    }
+
+    #[test]
+    fn it_keeps_raw_values_with_rows() {
+        let converter = ConvertOptions::new().numeric_column("foo").build();
+        let rows = converter
+            .rows("foo\n1\n".as_bytes())
+            .unwrap()
+            .collect::<Result<Vec<Row>>>()
+            .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].raw["foo"], "1");
+        assert_eq!(rows[0].value, json!({"foo": 1}));
+    }
+}
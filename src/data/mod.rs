@@ -1,41 +1,142 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone};
+use schema::ColumnSchema;
 use serde_json::{map::Entry, Map, Number, Value};
-use std::collections::{hash_map::Entry as HashMapEntry, HashMap};
+use std::collections::HashSet;
+use std::mem;
+use types::{
+    BooleanFormat, ColumnType, DateOutput, DateSettings, NumberFormat, TypeErrorPolicy,
+    TypeSettings,
+};
 
-pub fn group_numeric_arrays(value: Value) -> Value {
+// The formats tried for dates and datetimes when a column does not give any, after RFC 3339
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d"];
+const DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d",
+];
+
+/// How objects with only numeric keys are turned into arrays
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrayMode {
+    /// Sort the items by their index, the gaps between indices are ignored
+    Ordered,
+    /// Put each item at its index, filling any gaps with `null`
+    Positional,
+}
+
+/// The largest index [`ArrayMode::Positional`] puts an item at, objects with larger numeric keys
+/// such as `year.2024` are left as objects rather than being filled with thousands of `null`s
+pub const MAX_POSITION: u64 = 1000;
+
+pub fn group_numeric_arrays(value: Value, mode: ArrayMode) -> Value {
     match value {
-        Value::Object(object) => group_numeric_arrays_in_object(object),
-        Value::Array(arr) => group_numeric_arrays_in_array(arr),
+        Value::Object(object) => group_numeric_arrays_in_object(object, mode),
+        Value::Array(arr) => group_numeric_arrays_in_array(arr, mode),
         _ => value,
     }
 }
 
-fn group_numeric_arrays_in_object(object: Map<String, Value>) -> Value {
+fn group_numeric_arrays_in_object(object: Map<String, Value>, mode: ArrayMode) -> Value {
     // Recurse over each element in the object
     let object: Map<String, Value> = object
         .into_iter()
         .map(|(key, value)| {
-            let replacement = group_numeric_arrays(value);
+            let replacement = group_numeric_arrays(value, mode);
             (key, replacement)
         })
         .collect();
 
-    // Test if this object should be an array
-    let remaining_keys: Vec<bool> = object
-        .keys()
-        .filter(|k| k.parse::<u64>().is_err()) // Find anything the doesn't parse to u64
-        .map(|_| true)
+    // Test if this object should be an array, i.e. every key parses to u64
+    let indices: Option<Vec<u64>> = object.keys().map(|k| k.parse::<u64>().ok()).collect();
+    let indices = match indices {
+        Some(indices) => indices,
+        None => return json!(object),
+    };
+    if mode == ArrayMode::Positional && indices.iter().any(|&index| index > MAX_POSITION) {
+        return json!(object);
+    }
+
+    // Sort by the value of the index rather than the key, so 2 comes before 10
+    let mut items: Vec<(u64, Value)> = indices
+        .into_iter()
+        .zip(object.into_iter().map(|(_, v)| v))
         .collect();
+    items.sort_by_key(|(index, _)| *index);
 
-    if remaining_keys.is_empty() {
-        let values: Vec<Value> = object.values().map(|i| i.to_owned()).collect();
-        json!(values)
-    } else {
-        json!(object)
+    match mode {
+        ArrayMode::Ordered => items.into_iter().map(|(_, value)| value).collect(),
+        ArrayMode::Positional => {
+            let mut values: Vec<Value> = Vec::new();
+            items.into_iter().for_each(|(index, value)| {
+                let index = index as usize;
+                if index < values.len() {
+                    // The same index written differently, e.g. `1` and `01`
+                    let old_value = mem::replace(&mut values[index], Value::Null);
+                    values[index] = merge_values(old_value, value);
+                } else {
+                    values.resize(index, Value::Null);
+                    values.push(value);
+                }
+            });
+            json!(values)
+        }
     }
 }
 
-fn group_numeric_arrays_in_array(arr: Vec<Value>) -> Value {
-    arr.into_iter().map(group_numeric_arrays).collect()
+fn group_numeric_arrays_in_array(arr: Vec<Value>, mode: ArrayMode) -> Value {
+    arr.into_iter()
+        .map(|value| group_numeric_arrays(value, mode))
+        .collect()
+}
+
+// Find the JSON pointers of objects that have a mix of numeric and non-numeric keys, these are
+// left as objects by group_numeric_arrays which is probably not what was intended.
+pub fn mixed_numeric_keys(value: &Value) -> Vec<String> {
+    let mut pointers = Vec::new();
+    let mixed = |object: &Map<String, Value>| {
+        let numeric = object.keys().filter(|k| k.parse::<u64>().is_ok()).count();
+        numeric > 0 && numeric < object.len()
+    };
+    find_objects(value, String::new(), &mixed, &mut pointers);
+    pointers
+}
+
+// Find the JSON pointers of objects with numeric keys past MAX_POSITION, which are left as objects
+// by group_numeric_arrays in ArrayMode::Positional.
+pub fn positions_out_of_range(value: &Value) -> Vec<String> {
+    let mut pointers = Vec::new();
+    let out_of_range = |object: &Map<String, Value>| {
+        let indices: Option<Vec<u64>> = object.keys().map(|k| k.parse::<u64>().ok()).collect();
+        indices.is_some_and(|indices| indices.iter().any(|&i| i > MAX_POSITION))
+    };
+    find_objects(value, String::new(), &out_of_range, &mut pointers);
+    pointers
+}
+
+fn find_objects(
+    value: &Value,
+    pointer: String,
+    matches: &dyn Fn(&Map<String, Value>) -> bool,
+    pointers: &mut Vec<String>,
+) {
+    match value {
+        Value::Object(object) => {
+            if matches(object) {
+                pointers.push(pointer.clone());
+            }
+            object.iter().for_each(|(key, value)| {
+                let key = key.replace('~', "~0").replace('/', "~1");
+                find_objects(value, format!("{}/{}", pointer, key), matches, pointers);
+            });
+        }
+        Value::Array(arr) => arr.iter().enumerate().for_each(|(index, value)| {
+            find_objects(value, format!("{}/{}", pointer, index), matches, pointers);
+        }),
+        _ => {}
+    }
 }
 
 pub fn dimensional_converter(key: &str, value: &Value, ds: Option<&str>) -> (String, Value) {
@@ -43,7 +144,7 @@ pub fn dimensional_converter(key: &str, value: &Value, ds: Option<&str>) -> (Str
         if key.contains(separator) {
             let mut parts = key.split(separator);
             let this_key = parts.next().unwrap().to_owned();
-            let key_chain = parts.collect::<Vec<&str>>().join(".").to_owned();
+            let key_chain = parts.collect::<Vec<&str>>().join(separator);
             let (next_key, data) = dimensional_converter(&key_chain, value, Some(separator));
             return (this_key, json!({ next_key: data }));
         }
@@ -54,8 +155,9 @@ pub fn dimensional_converter(key: &str, value: &Value, ds: Option<&str>) -> (Str
 pub fn prepare_upsert(entry: Entry, data: Value) -> Value {
     match entry {
         Entry::Vacant(_) => data,
-        Entry::Occupied(e) => {
-            let old_value = e.remove();
+        Entry::Occupied(mut e) => {
+            // Take the old value rather than removing the key so that it keeps its position.
+            let old_value = mem::replace(e.get_mut(), Value::Null);
             merge_values(old_value, data)
         }
     }
@@ -113,16 +215,50 @@ fn remove_empty_strings_from_array(arr: Vec<Value>) -> Value {
     json!(new_arr)
 }
 
+// Items in arrays are kept, even if they are null, so that positional arrays keep their positions
+pub fn remove_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(object) => remove_nulls_from_object(object),
+        Value::Array(arr) => json!(arr.into_iter().map(remove_nulls).collect::<Vec<Value>>()),
+        _ => value,
+    }
+}
+
+fn remove_nulls_from_object(object: Map<String, Value>) -> Value {
+    let new_object: Map<String, Value> = object
+        .into_iter()
+        .map(|(key, value)| (key, remove_nulls(value)))
+        .filter(|(_key, value)| !value.is_null())
+        .collect();
+    json!(new_object)
+}
+
+// Sort the keys of every object alphabetically, rather than keeping the order of the columns.
+pub fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let mut entries: Vec<(String, Value)> = object
+                .into_iter()
+                .map(|(key, value)| (key, sort_keys(value)))
+                .collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(entries.into_iter().collect())
+        }
+        Value::Array(arr) => arr.into_iter().map(sort_keys).collect(),
+        _ => value,
+    }
+}
+
 fn merge_values(v1: Value, v2: Value) -> Value {
     // If both values are objects combine on keys
     if v1.is_object() && v2.is_object() {
         if let Value::Object(mut o1) = v1 {
-            if let Value::Object(mut o2) = v2 {
+            if let Value::Object(o2) = v2 {
                 o2.into_iter().for_each(|(key2, value2)| {
                     let replacement = match o1.entry(key2.to_owned()) {
                         Entry::Vacant(_) => value2,
-                        Entry::Occupied(e) => {
-                            let value1 = e.remove();
+                        Entry::Occupied(mut e) => {
+                            let value1 = mem::replace(e.get_mut(), Value::Null);
                             merge_values(value1, value2)
                         }
                     };
@@ -159,47 +295,7 @@ fn merge_values(v1: Value, v2: Value) -> Value {
     json!([v1, v2])
 }
 
-fn string_to_bool(string: &str) -> bool {
-    match string.to_lowercase().as_str() {
-        "" => false,
-        "0" => false,
-        "false" => false,
-        _ => true,
-    }
-}
-
-fn number_to_bool(number: &Number) -> bool {
-    if number.is_u64() {
-        number.as_u64().unwrap() != 0
-    } else if number.is_i64() {
-        number.as_i64().unwrap() != 0
-    } else if number.is_f64() {
-        number.as_f64().unwrap() != 0.0
-    } else {
-        panic!("serde_json have changed their api, it is no longer possible to determine the type of numbers")
-    }
-}
-
-pub fn value_to_bool(value: &Value) -> bool {
-    match value {
-        &Value::Null => false,
-        Value::Bool(boolean) => *boolean,
-        Value::Number(number) => number_to_bool(&number),
-        Value::String(string) => string_to_bool(&string),
-        Value::Array(array) => !array.is_empty(),
-        Value::Object(object) => !object.is_empty(),
-    }
-}
-
-fn boolean_to_number(boolean: bool) -> Number {
-    if boolean {
-        1.into()
-    } else {
-        0.into()
-    }
-}
-
-fn string_to_number(string: &str) -> Result<Number, &str> {
+pub(crate) fn string_to_number(string: &str) -> Result<Number, &str> {
     if string.is_empty() {
         let zero = json!(0);
         if let Value::Number(zero) = zero {
@@ -227,82 +323,444 @@ fn string_to_number(string: &str) -> Result<Number, &str> {
     Err(string)
 }
 
-pub fn value_to_number(value: &Value) -> Number {
-    match value {
-        &Value::Null => 0.into(),
-        Value::Bool(boolean) => boolean_to_number(*boolean),
-        Value::Number(number) => number.clone(),
-        Value::String(string) => {
-            string_to_number(&string).expect("Could not calculate numeric value of column")
-        }
-        Value::Array(array) => boolean_to_number(!array.is_empty()),
-        Value::Object(object) => boolean_to_number(!object.is_empty()),
-    }
-}
-
-pub fn row_to_values(row: &HashMap<String, String>) -> HashMap<String, Value> {
-    row.iter()
-        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+// Pair each value with its header, keeping the order of the columns.
+pub fn row_to_values(
+    headers: &csv::StringRecord,
+    record: &csv::StringRecord,
+) -> Map<String, Value> {
+    headers
+        .iter()
+        .zip(record.iter())
+        .map(|(key, value)| (key.to_owned(), Value::String(value.to_owned())))
         .collect()
 }
 
-pub fn columns_to_booleans(
-    columns: &[String],
-    mut row: HashMap<String, Value>,
-) -> HashMap<String, Value> {
-    columns.iter().for_each(|column| {
-        if let HashMapEntry::Occupied(entry) = row.entry(column.to_string()) {
-            *entry.into_mut() = Value::Bool(value_to_bool(entry.get()));
+// Replace values that stand for null in every column, such as NA or N/A
+pub fn replace_null_values(
+    null_values: &[String],
+    mut row: Map<String, Value>,
+) -> Map<String, Value> {
+    row.values_mut().for_each(|value| {
+        let is_null = value
+            .as_str()
+            .is_some_and(|string| null_values.iter().any(|null| null == string));
+        if is_null {
+            *value = Value::Null;
         }
     });
     row
 }
 
-pub fn columns_to_numbers(
-    columns: &[String],
-    mut row: HashMap<String, Value>,
-) -> HashMap<String, Value> {
-    columns.iter().for_each(|column| {
-        if let HashMapEntry::Occupied(entry) = row.entry(column.to_string()) {
-            *entry.into_mut() = Value::Number(value_to_number(entry.get()));
+// Replace empty values in each of the columns, e.g. with null or a default value.
+pub fn fill_empty_values(
+    columns: &[(String, Value)],
+    mut row: Map<String, Value>,
+) -> Map<String, Value> {
+    columns.iter().for_each(|(column, replacement)| {
+        if let Some(value) = row.get_mut(column) {
+            if value.as_str() == Some("") {
+                *value = replacement.clone();
+            }
         }
     });
     row
 }
 
-// Fold array of json objects (one object for each row) into one root object containing an
-// an array for each column, where the length of each array is equal to the # of rows.
-pub fn fold(mut items: Value, headers: &csv::StringRecord, ds: Option<&str>) -> Value {
-    // Clean up headers to account for dimensional separators.
-    let headers = distill_headers(headers, ds);
-    
-    // Initialize root object with an empty array for each column. 
-    let mut root_object = Value::Object(Map::new());
-    headers.into_iter().for_each( |header| {
-        root_object.as_object_mut().unwrap().insert(header.into(), Value::Array(Vec::new()));
-    });
-    
-    // Move each row into the arrays under the root object.
-    items.as_array_mut().unwrap().into_iter().for_each( |row| {
-        // Put empty values back into row.
-        root_object
-            .as_object_mut()
-            .unwrap()
-            .iter_mut()
-            .for_each( |(k,a)| {
-                match row.as_object_mut().unwrap().remove(k) {
-                    Some(v) => {
-                        a.as_array_mut().unwrap().push(v);
+// Convert columns to the types in their schema. Only strings are converted, anything else has
+// already been given a value, for example by fill_empty_values.
+pub fn columns_to_types(
+    columns: &[ColumnSchema],
+    settings: &TypeSettings,
+    mut row: Map<String, Value>,
+) -> Result<Map<String, Value>, String> {
+    for column in columns {
+        if let Entry::Occupied(entry) = row.entry(column.name.to_string()) {
+            let converted = match entry.get() {
+                Value::String(string) => match string_to_value(string, column, settings) {
+                    Ok(value) => value,
+                    Err(err) => match column.on_type_error.unwrap_or(settings.on_type_error) {
+                        TypeErrorPolicy::Fail => {
+                            return Err(format!("column {}: {}", column.name, err))
+                        }
+                        TypeErrorPolicy::Null => Value::Null,
+                        TypeErrorPolicy::Keep => continue,
+                        TypeErrorPolicy::Default => column
+                            .default
+                            .clone()
+                            .unwrap_or_else(|| settings.type_error_default.clone()),
                     },
-                    None => {
-                        a.as_array_mut().unwrap().push(Value::Null);
-                    }
-                }
-            });
+                },
+                _ => continue,
+            };
+            *entry.into_mut() = converted;
+        }
+    }
+    Ok(row)
+}
+
+pub(crate) fn string_to_value(
+    string: &str,
+    column: &ColumnSchema,
+    settings: &TypeSettings,
+) -> Result<Value, String> {
+    let value = Value::String(string.to_owned());
+    match column.column_type {
+        ColumnType::String => Ok(value),
+        ColumnType::Integer | ColumnType::Number | ColumnType::Float | ColumnType::Decimal => {
+            string_to_typed_number(string, column.column_type, &settings.numbers)
+        }
+        ColumnType::Boolean => string_to_boolean(string, &settings.booleans),
+        ColumnType::Date => string_to_date(string, &column.formats, &settings.dates),
+        ColumnType::Datetime => string_to_datetime(string, &column.formats, &settings.dates),
+        ColumnType::Json => serde_json::from_str(string)
+            .map_err(|err| format!("{:?} is not valid json: {}", string, err)),
+        ColumnType::Null => Ok(Value::Null),
+    }
+}
+
+fn string_to_boolean(string: &str, format: &BooleanFormat) -> Result<Value, String> {
+    let trimmed = string.trim();
+    if trimmed.is_empty() {
+        return Ok(if format.empty_is_null {
+            Value::Null
+        } else {
+            Value::Bool(false)
+        });
+    }
+    let lowercase = trimmed.to_lowercase();
+    let matches = |values: &[String]| values.iter().any(|value| value.to_lowercase() == lowercase);
+    if matches(&format.true_values) {
+        Ok(Value::Bool(true))
+    } else if matches(&format.false_values) {
+        Ok(Value::Bool(false))
+    } else if format.strict {
+        Err(format!("{:?} is not a boolean", string))
+    } else {
+        Ok(Value::Bool(true))
+    }
+}
+
+pub(crate) fn string_to_typed_number(
+    string: &str,
+    column_type: ColumnType,
+    format: &NumberFormat,
+) -> Result<Value, String> {
+    let digits = normalize_number(string, format);
+    let (number, expected) = match column_type {
+        ColumnType::Integer => (digits.and_then(|d| string_to_integer(&d)), "a whole number"),
+        ColumnType::Float => (digits.and_then(|d| string_to_float(&d)), "a number"),
+        ColumnType::Decimal => (
+            digits.and_then(|d| string_to_decimal(&d)),
+            "a decimal number",
+        ),
+        _ => (digits.and_then(|d| string_to_number(&d).ok()), "a number"),
+    };
+    number
+        .map(Value::Number)
+        .ok_or_else(|| format!("{:?} is not {}", string, expected))
+}
+
+// Rewrite a number the way json writes it, with a `.` before its decimals and without thousands
+// separators, currency symbols or a percent sign. Negative numbers can be in parentheses.
+fn normalize_number(string: &str, format: &NumberFormat) -> Option<String> {
+    let mut rest = string.trim();
+    if rest.is_empty() {
+        return Some(String::new());
+    }
+    let parenthesized = rest.starts_with('(') && rest.ends_with(')') && rest.len() > 1;
+    if parenthesized {
+        rest = rest[1..rest.len() - 1].trim();
+    }
+    let percent = rest.ends_with('%');
+    if percent {
+        rest = rest[..rest.len() - 1].trim_end();
+    }
+    // The sign can be either side of a currency symbol, as in -$5 or $-5
+    rest = rest.trim_end_matches(is_currency).trim_end();
+    let (mut sign, mut unsigned) = split_sign(rest);
+    unsigned = unsigned.trim_start_matches(is_currency).trim_start();
+    if sign.is_none() {
+        let (inner_sign, inner) = split_sign(unsigned);
+        sign = inner_sign;
+        unsigned = inner;
+    }
+    if unsigned.is_empty() || (parenthesized && sign.is_some()) {
+        return None;
+    }
+
+    let mut digits = String::with_capacity(unsigned.len() + 1);
+    if parenthesized || sign == Some('-') {
+        digits.push('-');
+    }
+    // Separators only go between the digits of the whole number, with groups of three digits
+    // after the last one. Earlier groups can have two, as in 1,00,000.
+    let mut decimals = false;
+    let mut group: Option<usize> = None;
+    for c in unsigned.chars() {
+        if c.is_ascii_digit() {
+            group = group.map(|digits| digits + 1);
+            digits.push(c);
+            continue;
+        }
+        if is_thousands_separator(c, format) {
+            let follows_digits = match group {
+                Some(digits) => digits == 2 || digits == 3,
+                None => digits.ends_with(|d: char| d.is_ascii_digit()),
+            };
+            if decimals || !follows_digits {
+                return None;
+            }
+            group = Some(0);
+            continue;
+        }
+        if group.take().is_some_and(|digits| digits != 3) {
+            return None;
+        }
+        if c == format.decimal_separator && !decimals {
+            decimals = true;
+            digits.push('.');
+        } else if c == '.' {
+            return None;
+        } else {
+            digits.push(c);
+        }
+    }
+    if group.is_some_and(|digits| digits != 3) {
+        return None;
+    }
+    if percent && format.divide_percentages {
+        divide_by_hundred(&digits)
+    } else {
+        Some(digits)
+    }
+}
+
+fn split_sign(string: &str) -> (Option<char>, &str) {
+    match string.chars().next() {
+        Some(sign) if sign == '-' || sign == '+' => (Some(sign), string[1..].trim_start()),
+        _ => (None, string),
+    }
+}
+
+fn is_currency(c: char) -> bool {
+    matches!(
+        c,
+        '$' | '¢' | '£' | '¤' | '¥' | '֏' | '؋' | '৳' | '฿' | '៛' | '﷼'
+    ) || ('\u{20A0}'..='\u{20CF}').contains(&c)
+}
+
+fn is_thousands_separator(c: char, format: &NumberFormat) -> bool {
+    match format.thousands_separator {
+        Some(separator) if separator.is_whitespace() => c.is_whitespace(),
+        Some(separator) => c == separator,
+        None => false,
+    }
+}
+
+// Move the decimal point rather than doing the division, so that decimals stay exact
+fn divide_by_hundred(number: &str) -> Option<String> {
+    if let Some(index) = number.find(['e', 'E']) {
+        let exponent: i64 = number[index + 1..].parse().ok()?;
+        return Some(format!("{}{}", &number[..=index], exponent - 2));
+    }
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", number),
+    };
+    let (whole, fraction) = match unsigned.find('.') {
+        Some(index) => (&unsigned[..index], &unsigned[index + 1..]),
+        None => (unsigned, ""),
+    };
+    let whole = format!("{:0>3}", whole.trim_start_matches('0'));
+    let (whole, hundredths) = whole.split_at(whole.len() - 2);
+    Some(format!("{}{}.{}{}", sign, whole, hundredths, fraction))
+}
+
+fn string_to_integer(string: &str) -> Option<Number> {
+    if string.is_empty() {
+        return Some(Number::from(0));
+    }
+    string
+        .parse::<u64>()
+        .map(Number::from)
+        .or_else(|_| string.parse::<i64>().map(Number::from))
+        .ok()
+}
+
+fn string_to_float(string: &str) -> Option<Number> {
+    if string.is_empty() {
+        return Number::from_f64(0.0);
+    }
+    string.parse::<f64>().ok().and_then(Number::from_f64)
+}
+
+// Keeps every digit and the exponent exactly as they are written. Only what json does not allow is
+// changed: a leading dot gets a zero, a trailing dot and leading zeros are dropped.
+fn string_to_decimal(string: &str) -> Option<Number> {
+    if string.is_empty() {
+        return Some(Number::from(0));
+    }
+    let (sign, unsigned) = match string.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", string),
+    };
+    let (mantissa, exponent) =
+        unsigned.split_at(unsigned.find(['e', 'E']).unwrap_or(unsigned.len()));
+    let (whole, fraction) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+        None => (mantissa, ""),
+    };
+    if whole.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    let whole = match whole.trim_start_matches('0') {
+        "" => "0",
+        whole => whole,
+    };
+    let mut decimal = format!("{}{}", sign, whole);
+    if !fraction.is_empty() {
+        decimal.push('.');
+        decimal.push_str(fraction);
+    }
+    decimal.push_str(exponent);
+    string_to_exact_number(decimal)
+}
+
+// Parsing would write the exponent as e+3 rather than as it was written
+#[cfg(feature = "arbitrary_precision")]
+fn string_to_exact_number(decimal: String) -> Option<Number> {
+    serde_json::from_str::<Number>(&decimal).ok()?;
+    Some(Number::from_string_unchecked(decimal))
+}
+
+// Without arbitrary precision a number can only be as exact as a float
+#[cfg(not(feature = "arbitrary_precision"))]
+fn string_to_exact_number(decimal: String) -> Option<Number> {
+    serde_json::from_str::<Number>(&decimal).ok()
+}
+
+fn string_to_date(string: &str, formats: &[String], dates: &DateSettings) -> Result<Value, String> {
+    let date = formats_or(formats, DATE_FORMATS)
+        .find_map(|format| NaiveDate::parse_from_str(string, format).ok())
+        .ok_or_else(|| format!("{:?} is not a date", string))?;
+    match dates.output {
+        DateOutput::Iso => Ok(json!(date.format("%Y-%m-%d").to_string())),
+        output => Ok(timestamp(
+            with_timezone(date.and_hms_opt(0, 0, 0).unwrap(), dates.timezone),
+            output,
+        )),
+    }
+}
+
+fn string_to_datetime(
+    string: &str,
+    formats: &[String],
+    dates: &DateSettings,
+) -> Result<Value, String> {
+    // Values with an offset keep it, others are in the timezone if there is one
+    let parsed = if formats.is_empty() {
+        DateTime::parse_from_rfc3339(string).ok().map(Ok)
+    } else {
+        None
+    };
+    let parsed = parsed
+        .or_else(|| {
+            formats_or(formats, DATETIME_FORMATS).find_map(|format| {
+                DateTime::parse_from_str(string, format)
+                    .map(Ok)
+                    .or_else(|_| NaiveDateTime::parse_from_str(string, format).map(Err))
+                    .or_else(|_| {
+                        NaiveDate::parse_from_str(string, format)
+                            .map(|date| Err(date.and_hms_opt(0, 0, 0).unwrap()))
+                    })
+                    .ok()
+            })
+        })
+        .ok_or_else(|| format!("{:?} is not a datetime", string))?;
+    let datetime = parsed.or_else(|naive| match dates.timezone {
+        Some(timezone) => Ok(with_timezone(naive, Some(timezone))),
+        None => Err(naive),
     });
-    
-    // All done.
-    return root_object;
+    match (datetime, dates.output) {
+        (Ok(datetime), DateOutput::Iso) => {
+            Ok(json!(datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true)))
+        }
+        (Err(naive), DateOutput::Iso) => {
+            Ok(json!(naive.format("%Y-%m-%dT%H:%M:%S%.f").to_string()))
+        }
+        (Ok(datetime), output) => Ok(timestamp(datetime, output)),
+        (Err(naive), output) => Ok(timestamp(with_timezone(naive, None), output)),
+    }
+}
+
+fn formats_or<'a>(
+    formats: &'a [String],
+    defaults: &'a [&'a str],
+) -> Box<dyn Iterator<Item = &'a str> + 'a> {
+    if formats.is_empty() {
+        Box::new(defaults.iter().cloned())
+    } else {
+        Box::new(formats.iter().map(String::as_str))
+    }
+}
+
+// Put a date and time without an offset in the timezone, or UTC
+fn with_timezone(naive: NaiveDateTime, timezone: Option<FixedOffset>) -> DateTime<FixedOffset> {
+    let timezone = timezone.unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    timezone.from_local_datetime(&naive).unwrap()
+}
+
+fn timestamp(datetime: DateTime<FixedOffset>, output: DateOutput) -> Value {
+    match output {
+        DateOutput::Milliseconds => json!(datetime.timestamp_millis()),
+        _ => json!(datetime.timestamp()),
+    }
+}
+
+// Fold array of json objects (one object for each row) into one root object containing an
+// an array for each column, where the length of each array is equal to the # of rows.
+pub fn fold(items: Value, headers: &csv::StringRecord, ds: Option<&str>) -> Value {
+    let mut folder = Folder::new(headers, ds);
+    if let Value::Array(rows) = items {
+        rows.into_iter().for_each(|row| folder.push(row));
+    }
+    folder.finish()
+}
+
+/// Folds rows one at a time, so only the column arrays need to be held in memory rather than
+/// every row.
+pub struct Folder {
+    root_object: Map<String, Value>,
+}
+
+impl Folder {
+    pub fn new(headers: &csv::StringRecord, ds: Option<&str>) -> Self {
+        // Clean up headers to account for dimensional separators.
+        let headers = distill_headers(headers, ds);
+
+        // Initialize root object with an empty array for each column.
+        let root_object = headers
+            .into_iter()
+            .map(|header| (header, Value::Array(Vec::new())))
+            .collect();
+        Folder { root_object }
+    }
+
+    // Move the row into the arrays under the root object.
+    pub fn push(&mut self, mut row: Value) {
+        self.root_object.iter_mut().for_each(|(k, a)| {
+            // Put empty values back into row.
+            let v = row
+                .as_object_mut()
+                .and_then(|row| row.remove(k))
+                .unwrap_or(Value::Null);
+            a.as_array_mut().unwrap().push(v);
+        });
+    }
+
+    pub fn finish(self) -> Value {
+        Value::Object(self.root_object)
+    }
 }
 
 // If we were called with a separator, remove separated items from header.
@@ -315,9 +773,9 @@ fn distill_headers(headers: &csv::StringRecord, ds: Option<&str>) -> Vec<String>
                 |s| s.split(ds).next().unwrap().into()
             ).collect();
             
-            // Then remove duplicates.
-            headers.sort();
-            headers.dedup();
+            // Then remove duplicates, keeping the order the headers first appear in.
+            let mut seen = HashSet::new();
+            headers.retain(|header| seen.insert(header.clone()));
             headers
         },
         None => {
@@ -327,6 +785,78 @@ fn distill_headers(headers: &csv::StringRecord, ds: Option<&str>) -> Vec<String>
     }
 }
 
+// Flatten a json object into a header and a csv value for each field, the reverse of
+// dimensional_converter and group_numeric_arrays. Array items are given their index as a key.
+// Without a separator, nested objects and arrays are written as json.
+pub fn flatten(value: &Value, ds: Option<&str>) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    if let Value::Object(object) = value {
+        object
+            .iter()
+            .for_each(|(key, value)| flatten_into(key.clone(), value, ds, &mut fields));
+    }
+    fields
+}
+
+fn flatten_into(key: String, value: &Value, ds: Option<&str>, fields: &mut Vec<(String, String)>) {
+    let children: Vec<(String, &Value)> = match (value, ds) {
+        (Value::Object(object), Some(_)) => object.iter().map(|(k, v)| (k.clone(), v)).collect(),
+        (Value::Array(items), Some(_)) => items
+            .iter()
+            .enumerate()
+            .map(|(index, v)| (index.to_string(), v))
+            .collect(),
+        _ => return fields.push((key, value_to_field(value))),
+    };
+    let ds = ds.unwrap();
+    children.into_iter().for_each(|(child, value)| {
+        flatten_into(format!("{}{}{}", key, ds, child), value, Some(ds), fields)
+    });
+}
+
+fn value_to_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+// Whether the value is clearly the output of fold, an object with more than one column that all
+// have an array of the same length. A single row with arrays in it can look the same, so one
+// column is only unfolded when the json is known to be folded.
+pub fn is_folded(value: &Value) -> bool {
+    let object = match value {
+        Value::Object(object) if object.len() > 1 => object,
+        _ => return false,
+    };
+    let mut lengths = object
+        .values()
+        .map(|column| column.as_array().map(Vec::len));
+    let first = lengths.next().unwrap();
+    first.is_some() && lengths.all(|length| length == first)
+}
+
+// Split a folded object back into an object for each row, the reverse of fold.
+pub fn unfold(folded: Value) -> Vec<Value> {
+    let folded = match folded {
+        Value::Object(folded) => folded,
+        _ => return vec![],
+    };
+    let mut rows: Vec<Map<String, Value>> = Vec::new();
+    folded.into_iter().for_each(|(key, column)| {
+        if let Value::Array(items) = column {
+            if rows.len() < items.len() {
+                rows.resize(items.len(), Map::new());
+            }
+            rows.iter_mut().zip(items).for_each(|(row, item)| {
+                row.insert(key.clone(), item);
+            });
+        }
+    });
+    rows.into_iter().map(Value::Object).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -352,6 +882,15 @@ mod tests {
             )
         }
 
+        #[test]
+        fn it_splits_every_level_on_the_separator() {
+            let value = json!("value");
+            assert_eq!(
+                super::dimensional_converter("first_second_third", &value, Some("_")),
+                (String::from("first"), json!({"second":{"third":&value}}))
+            )
+        }
+
         #[test]
         fn it_does_simple_json_conversion_when_seperator_not_found() {
             let key = String::from("first.second.third");
@@ -363,6 +902,82 @@ mod tests {
         }
     }
 
+    mod group_numeric_arrays {
+        use super::ArrayMode;
+
+        #[test]
+        fn it_orders_items_by_their_numeric_index() {
+            let value = json!({"pets": {"10": "c", "2": "b", "1": "a"}});
+            assert_eq!(
+                super::group_numeric_arrays(value, ArrayMode::Ordered),
+                json!({"pets": ["a", "b", "c"]})
+            );
+        }
+
+        #[test]
+        fn it_fills_gaps_with_null_for_positional_arrays() {
+            let value = json!({"pets": {"3": "b", "0": "a"}});
+            assert_eq!(
+                super::group_numeric_arrays(value, ArrayMode::Positional),
+                json!({"pets": ["a", null, null, "b"]})
+            );
+        }
+
+        #[test]
+        fn it_leaves_mixed_keys_as_an_object() {
+            let value = json!({"pets": {"1": "a", "name": "b"}});
+            assert_eq!(
+                super::group_numeric_arrays(value.clone(), ArrayMode::Ordered),
+                value
+            );
+            assert_eq!(super::mixed_numeric_keys(&value), vec!["/pets"]);
+        }
+
+        #[test]
+        fn it_leaves_positions_past_the_limit_as_an_object() {
+            let value = json!({"year": {"2024": 1, "0": 2}, "p": {"18446744073709551615": 3}});
+            assert_eq!(
+                super::group_numeric_arrays(value.clone(), ArrayMode::Positional),
+                value
+            );
+            assert_eq!(super::positions_out_of_range(&value), vec!["/year", "/p"]);
+        }
+    }
+
+    mod prepare_upsert {
+        use super::Map;
+
+        #[test]
+        fn it_keeps_keys_in_the_order_they_were_first_inserted() {
+            let mut items = Map::new();
+            for (key, value) in [
+                ("b", json!({"y": 1})),
+                ("a", json!("a")),
+                ("b", json!({"x": 2})),
+                ("c", json!("c")),
+                ("b", json!({"y": 3})),
+            ] {
+                let value = super::prepare_upsert(items.entry(key), value);
+                items.insert(key.to_owned(), value);
+            }
+            let keys: Vec<&String> = items.keys().collect();
+            assert_eq!(keys, vec!["b", "a", "c"]);
+            let keys: Vec<&String> = items["b"].as_object().unwrap().keys().collect();
+            assert_eq!(keys, vec!["y", "x"]);
+        }
+    }
+
+    mod sort_keys {
+        #[test]
+        fn it_sorts_nested_keys() {
+            let sorted = super::sort_keys(json!({"b": [{"d": 1, "c": 2}], "a": {"f": 3, "e": 4}}));
+            assert_eq!(
+                serde_json::to_string(&sorted).unwrap(),
+                r#"{"a":{"e":4,"f":3},"b":[{"c":2,"d":1}]}"#
+            );
+        }
+    }
+
     mod merge_values {
         #[test]
         fn it_merges_scalars_correctly() {
@@ -404,99 +1019,377 @@ mod tests {
         }
     }
 
-    mod number_to_bool {
+    mod fill_empty_values {
+        use super::Map;
+
         #[test]
-        fn it_converts_u64s() {
-            let zero: u64 = 0;
-            let one: u64 = 1;
+        fn it_only_replaces_empty_values() {
+            let mut row = Map::new();
+            row.insert(String::from("age"), json!(""));
+            row.insert(String::from("name"), json!("daniel"));
+            let columns = vec![
+                (String::from("age"), json!(null)),
+                (String::from("name"), json!("unknown")),
+            ];
+            let row = super::fill_empty_values(&columns, row);
+            assert_eq!(json!(row), json!({"age": null, "name": "daniel"}));
+        }
+    }
 
-            let zero = match json!(zero) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
-            };
-            let one = match json!(one) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
-            };
+    mod replace_null_values {
+        use super::Map;
 
-            assert!(!super::number_to_bool(&zero));
-            assert!(super::number_to_bool(&one));
+        #[test]
+        fn it_only_replaces_the_null_values() {
+            let mut row = Map::new();
+            row.insert(String::from("age"), json!("N/A"));
+            row.insert(String::from("name"), json!("NA"));
+            row.insert(String::from("score"), json!("-1"));
+            let null_values = vec![String::from("N/A"), String::from("-")];
+            let row = super::replace_null_values(&null_values, row);
+            assert_eq!(
+                json!(row),
+                json!({"age": null, "name": "NA", "score": "-1"})
+            );
         }
+    }
 
+    mod remove_nulls {
         #[test]
-        fn it_converts_i64s() {
-            let zero: i64 = 0;
-            let one: i64 = 1;
-            let minus_one: i64 = 1;
+        fn it_removes_null_keys_but_not_array_items() {
+            let value = json!({"a": null, "b": {"c": null, "d": 1}, "e": [null, 2]});
+            assert_eq!(
+                super::remove_nulls(value),
+                json!({"b": {"d": 1}, "e": [null, 2]})
+            );
+        }
+    }
+
+    mod columns_to_types {
+        use super::{
+            BooleanFormat, ColumnSchema, ColumnType, DateOutput, DateSettings, Map, NumberFormat,
+            TypeErrorPolicy, TypeSettings, Value,
+        };
+        use types::parse_timezone;
+
+        fn columns(columns: &[(&str, ColumnType)]) -> Vec<ColumnSchema> {
+            columns
+                .iter()
+                .map(|(name, column_type)| ColumnSchema::new(*name, *column_type))
+                .collect()
+        }
 
-            let zero = match json!(zero) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+        fn dated(formats: &[&str], column_type: ColumnType) -> Vec<ColumnSchema> {
+            vec![ColumnSchema {
+                formats: formats.iter().map(|format| format.to_string()).collect(),
+                ..ColumnSchema::new("at", column_type)
+            }]
+        }
+
+        fn convert(value: &str, columns: &[ColumnSchema], dates: DateSettings) -> String {
+            let mut row = Map::new();
+            row.insert(String::from("at"), json!(value));
+            let settings = TypeSettings {
+                dates,
+                ..Default::default()
             };
-            let one = match json!(one) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+            let row = super::columns_to_types(columns, &settings, row).unwrap();
+            row["at"].to_string()
+        }
+
+        #[test]
+        fn it_converts_each_column_to_its_type() {
+            let mut row = Map::new();
+            row.insert(String::from("age"), json!("34"));
+            row.insert(String::from("active"), json!("true"));
+            row.insert(String::from("name"), json!(""));
+            row.insert(String::from("price"), json!("2"));
+            row.insert(String::from("born"), json!("1985-10-26"));
+            row.insert(String::from("tags"), json!("[\"a\", \"b\"]"));
+            row.insert(String::from("score"), json!(null));
+            let columns = columns(&[
+                ("age", ColumnType::Integer),
+                ("active", ColumnType::Boolean),
+                ("name", ColumnType::String),
+                ("price", ColumnType::Float),
+                ("born", ColumnType::Date),
+                ("tags", ColumnType::Json),
+                ("score", ColumnType::Number),
+            ]);
+            let row = super::columns_to_types(&columns, &TypeSettings::default(), row).unwrap();
+            assert_eq!(
+                serde_json::to_string(&row).unwrap(),
+                r#"{"age":34,"active":true,"name":"","price":2.0,"born":"1985-10-26","tags":["a","b"],"score":null}"#
+            );
+        }
+
+        #[test]
+        fn it_rejects_invalid_values() {
+            let mut row = Map::new();
+            row.insert(String::from("born"), json!("26/10/1985"));
+            row.insert(String::from("tags"), json!("[a"));
+            let settings = TypeSettings::default();
+            let born = columns(&[("born", ColumnType::Date)]);
+            assert!(super::columns_to_types(&born, &settings, row.clone()).is_err());
+            let tags = columns(&[("tags", ColumnType::Json)]);
+            assert!(super::columns_to_types(&tags, &settings, row).is_err());
+        }
+
+        #[test]
+        fn it_only_accepts_whole_numbers_as_integers() {
+            let integer = columns(&[("at", ColumnType::Integer)]);
+            let dates = DateSettings::default();
+            assert_eq!(convert("-12", &integer, dates), "-12");
+            let mut row = Map::new();
+            row.insert(String::from("at"), json!("1.5"));
+            assert_eq!(
+                super::columns_to_types(&integer, &TypeSettings::default(), row),
+                Err(String::from(r#"column at: "1.5" is not a whole number"#))
+            );
+        }
+
+        #[test]
+        fn it_reads_the_true_and_false_values() {
+            let boolean = columns(&[("at", ColumnType::Boolean)]);
+            let dates = DateSettings::default();
+            for (value, expected) in &[
+                ("Yes", "true"),
+                ("N", "false"),
+                ("off", "false"),
+                ("f", "false"),
+                ("T", "true"),
+                (" 0 ", "false"),
+                ("", "false"),
+            ] {
+                assert_eq!(convert(value, &boolean, dates), *expected);
+            }
+            let format = BooleanFormat {
+                empty_is_null: true,
+                ..Default::default()
             };
-            let minus_one = match json!(minus_one) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+            assert_eq!(super::string_to_boolean("", &format), Ok(Value::Null));
+            assert_eq!(
+                super::string_to_boolean("nope", &format),
+                Err(String::from(r#""nope" is not a boolean"#))
+            );
+            let lenient = BooleanFormat {
+                strict: false,
+                ..Default::default()
             };
+            assert_eq!(
+                super::string_to_boolean("nope", &lenient),
+                Ok(Value::Bool(true))
+            );
+        }
 
-            assert!(!super::number_to_bool(&zero));
-            assert!(super::number_to_bool(&one));
-            assert!(super::number_to_bool(&minus_one));
+        #[test]
+        fn it_always_writes_floats_with_a_decimal_point() {
+            let float = columns(&[("at", ColumnType::Float)]);
+            let dates = DateSettings::default();
+            assert_eq!(convert("1", &float, dates), "1.0");
+            assert_eq!(convert("1.5", &float, dates), "1.5");
         }
 
         #[test]
-        fn it_converts_f64s() {
-            let zero: f64 = 0.0;
-            let one: f64 = 1.0;
-            let huge: f64 = 1.0e+40;
-            let tiny: f64 = 1.0e-40;
+        fn it_keeps_decimals_exactly_as_they_are_written() {
+            let decimal = columns(&[("at", ColumnType::Decimal)]);
+            let dates = DateSettings::default();
+            assert_eq!(convert("0.10", &decimal, dates), "0.10");
+            assert_eq!(convert(".5", &decimal, dates), "0.5");
+            assert_eq!(convert("-.5", &decimal, dates), "-0.5");
+            assert_eq!(convert("5.", &decimal, dates), "5");
+            assert_eq!(convert("007.10", &decimal, dates), "7.10");
+            assert_eq!(convert("000", &decimal, dates), "0");
+            assert_eq!(convert("1E3", &decimal, dates), "1E3");
+            assert_eq!(convert("1.50e-2", &decimal, dates), "1.50e-2");
+            assert_eq!(
+                convert("-12345678901234567.89", &decimal, dates),
+                "-12345678901234567.89"
+            );
+            for string in &["1,5", "0x10", "NaN", ".", "1e", "1.5.0"] {
+                assert_eq!(
+                    super::string_to_typed_number(
+                        string,
+                        ColumnType::Decimal,
+                        &NumberFormat::default()
+                    ),
+                    Err(format!("{:?} is not a decimal number", string))
+                );
+            }
+        }
 
-            let zero = match json!(zero) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+        #[test]
+        fn it_reads_numbers_in_the_number_format() {
+            let numbers = columns(&[("at", ColumnType::Number)]);
+            let settings = |format| TypeSettings {
+                numbers: format,
+                ..Default::default()
             };
-            let one = match json!(one) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+            let convert = |value: &str, format| {
+                super::string_to_value(value, &numbers[0], &settings(format)).map(|v| v.to_string())
             };
-            let huge = match json!(huge) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+            let plain = NumberFormat::default();
+            let german = NumberFormat::from_locale("de").unwrap();
+            let french = NumberFormat::from_locale("fr").unwrap();
+            assert_eq!(convert("1.234,56", german), Ok(String::from("1234.56")));
+            assert_eq!(
+                convert("1\u{a0}234,56", french),
+                Ok(String::from("1234.56"))
+            );
+            assert_eq!(convert(" (123) ", plain), Ok(String::from("-123")));
+            assert_eq!(
+                convert("-$1,234", NumberFormat::from_locale("en").unwrap()),
+                Ok(String::from("-1234"))
+            );
+            assert_eq!(convert("12,50 €", german), Ok(String::from("12.5")));
+            assert_eq!(convert("50%", plain), Ok(String::from("50")));
+            assert!(convert("1,234", plain).is_err());
+            assert!(convert("1.5", german).is_err());
+            assert!(convert("1,2.5", french).is_err());
+            assert!(convert("1.23,5", german).is_err());
+            assert_eq!(
+                convert("1,00,000.5", NumberFormat::from_locale("en").unwrap()),
+                Ok(String::from("100000.5"))
+            );
+            assert!(convert("$", plain).is_err());
+            assert!(convert("(-1)", plain).is_err());
+        }
+
+        #[test]
+        fn it_divides_percentages_by_moving_the_decimal_point() {
+            let decimal = columns(&[("at", ColumnType::Decimal)]);
+            let settings = TypeSettings {
+                numbers: NumberFormat {
+                    divide_percentages: true,
+                    ..Default::default()
+                },
+                ..Default::default()
             };
-            let tiny = match json!(tiny) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+            let convert = |value| {
+                super::string_to_value(value, &decimal[0], &settings)
+                    .unwrap()
+                    .to_string()
             };
+            assert_eq!(convert("50%"), "0.50");
+            assert_eq!(convert("5%"), "0.05");
+            assert_eq!(convert("-123.4 %"), "-1.234");
+            assert_eq!(convert("0.5%"), "0.005");
+            assert_eq!(convert("1e3%"), "1e1");
+            assert_eq!(convert("1E3%"), "1E1");
+            assert_eq!(convert("12"), "12");
+        }
 
-            assert!(!super::number_to_bool(&zero));
-            assert!(super::number_to_bool(&one));
-            assert!(super::number_to_bool(&huge));
-            assert!(super::number_to_bool(&tiny));
+        #[test]
+        fn it_uses_null_for_values_that_do_not_fit_if_asked() {
+            let mut row = Map::new();
+            row.insert(String::from("age"), json!("n/a"));
+            row.insert(String::from("price"), json!("free"));
+            let mut columns =
+                columns(&[("age", ColumnType::Integer), ("price", ColumnType::Float)]);
+            columns[1].on_type_error = Some(TypeErrorPolicy::Fail);
+            let settings = TypeSettings {
+                on_type_error: TypeErrorPolicy::Null,
+                ..Default::default()
+            };
+            assert_eq!(
+                super::columns_to_types(&columns, &settings, row.clone()),
+                Err(String::from(r#"column price: "free" is not a number"#))
+            );
+            columns.pop();
+            let row = super::columns_to_types(&columns, &settings, row).unwrap();
+            assert_eq!(row["age"], json!(null));
         }
-    }
 
-    mod boolean_to_number {
         #[test]
-        fn it_converts_true_to_one() {
-            let b = true;
-            let one = match json!(1) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+        fn it_keeps_values_that_do_not_fit_or_uses_a_default_if_asked() {
+            let mut row = Map::new();
+            row.insert(String::from("age"), json!("n/a"));
+            row.insert(String::from("price"), json!("free"));
+            row.insert(String::from("active"), json!("maybe"));
+            let mut columns = columns(&[
+                ("age", ColumnType::Integer),
+                ("price", ColumnType::Float),
+                ("active", ColumnType::Boolean),
+            ]);
+            columns[0].on_type_error = Some(TypeErrorPolicy::Keep);
+            columns[1].default = Some(json!(0));
+            let settings = TypeSettings {
+                on_type_error: TypeErrorPolicy::Default,
+                type_error_default: json!(false),
+                ..Default::default()
             };
-            assert_eq!(super::boolean_to_number(b), one)
+            let row = super::columns_to_types(&columns, &settings, row).unwrap();
+            assert_eq!(row["age"], json!("n/a"));
+            assert_eq!(row["price"], json!(0));
+            assert_eq!(row["active"], json!(false));
+        }
+
+        #[test]
+        fn it_parses_dates_with_any_of_the_formats() {
+            let columns = dated(&["%d/%m/%Y", "%Y-%m-%d"], ColumnType::Date);
+            let dates = DateSettings::default();
+            assert_eq!(convert("03/04/2021", &columns, dates), r#""2021-04-03""#);
+            assert_eq!(convert("2021-04-03", &columns, dates), r#""2021-04-03""#);
         }
 
         #[test]
-        fn it_converts_false_to_zero() {
-            let b = false;
-            let zero = match json!(0) {
-                super::Value::Number(num) => num,
-                _ => panic!("Number not created correctly"),
+        fn it_parses_datetimes() {
+            let columns = dated(&[], ColumnType::Datetime);
+            let dates = DateSettings::default();
+            assert_eq!(
+                convert("2021-04-03T10:00", &columns, dates),
+                r#""2021-04-03T10:00:00""#
+            );
+            assert_eq!(
+                convert("2021-04-03 10:00:00.5", &columns, dates),
+                r#""2021-04-03T10:00:00.500""#
+            );
+            assert_eq!(
+                convert("2021-04-03T10:00:00+02:00", &columns, dates),
+                r#""2021-04-03T10:00:00+02:00""#
+            );
+            let columns = dated(&["%d/%m/%Y %H:%M", "%d/%m/%Y"], ColumnType::Datetime);
+            assert_eq!(
+                convert("03/04/2021", &columns, dates),
+                r#""2021-04-03T00:00:00""#
+            );
+        }
+
+        #[test]
+        fn it_puts_naive_datetimes_in_the_timezone() {
+            let columns = dated(&[], ColumnType::Datetime);
+            let dates = DateSettings {
+                timezone: Some(parse_timezone("+02:00").unwrap()),
+                ..Default::default()
             };
-            assert_eq!(super::boolean_to_number(b), zero)
+            assert_eq!(
+                convert("2021-04-03T10:00", &columns, dates),
+                r#""2021-04-03T10:00:00+02:00""#
+            );
+            assert_eq!(
+                convert("2021-04-03T10:00:00Z", &columns, dates),
+                r#""2021-04-03T10:00:00Z""#
+            );
+        }
+
+        #[test]
+        fn it_writes_timestamps() {
+            let seconds = DateSettings {
+                output: DateOutput::Seconds,
+                ..Default::default()
+            };
+            let milliseconds = DateSettings {
+                output: DateOutput::Milliseconds,
+                timezone: Some(parse_timezone("+01:00").unwrap()),
+            };
+            let datetime = dated(&[], ColumnType::Datetime);
+            assert_eq!(convert("1970-01-01T00:01", &datetime, seconds), "60");
+            assert_eq!(
+                convert("1970-01-01T01:00:01", &datetime, milliseconds),
+                "1000"
+            );
+            let date = dated(&[], ColumnType::Date);
+            assert_eq!(convert("1970-01-02", &date, seconds), "86400");
         }
     }
 
@@ -548,8 +1441,7 @@ mod tests {
             let headers = vec!["foo.1", "foo.2", "bar"];
             let csv_headers = csv::StringRecord::from(headers);
             let csv_headers = super::distill_headers(&csv_headers, Some("."));
-            let mut headers = vec!["foo", "bar"];
-            headers.sort();
+            let headers = vec!["foo", "bar"];
             let matching = csv_headers.iter().zip(headers).filter(|&(a,b)| a == b).count(); 
             assert_eq!(matching, 2);
         }
@@ -620,4 +1512,60 @@ mod tests {
             );
         }
     }
+
+    mod flatten {
+        #[test]
+        fn it_joins_keys_and_array_indices_with_the_separator() {
+            let value = json!({
+                "name": {"first": "daniel", "last": null},
+                "tags": ["a", {"b": true}],
+                "age": 34
+            });
+            assert_eq!(
+                super::flatten(&value, Some("_")),
+                vec![
+                    (String::from("name_first"), String::from("daniel")),
+                    (String::from("name_last"), String::new()),
+                    (String::from("tags_0"), String::from("a")),
+                    (String::from("tags_1_b"), String::from("true")),
+                    (String::from("age"), String::from("34")),
+                ]
+            )
+        }
+
+        #[test]
+        fn it_writes_nested_values_as_json_without_a_separator() {
+            let value = json!({"name": {"first": "daniel"}, "tags": [1, 2]});
+            assert_eq!(
+                super::flatten(&value, None),
+                vec![
+                    (String::from("name"), String::from(r#"{"first":"daniel"}"#)),
+                    (String::from("tags"), String::from("[1,2]")),
+                ]
+            )
+        }
+    }
+
+    mod unfold {
+        #[test]
+        fn it_splits_folded_columns_into_rows() {
+            let folded = json!({"name": ["daniel", "alex"], "age": [34, null]});
+            assert!(super::is_folded(&folded));
+            assert_eq!(
+                super::unfold(folded),
+                vec![
+                    json!({"name": "daniel", "age": 34}),
+                    json!({"name": "alex", "age": null}),
+                ]
+            )
+        }
+
+        #[test]
+        fn it_only_unfolds_objects_of_arrays() {
+            assert!(!super::is_folded(&json!({"name": ["daniel"], "age": 34})));
+            assert!(!super::is_folded(&json!([{"name": "daniel"}])));
+            assert!(!super::is_folded(&json!({"tags": ["a", "b"]})));
+            assert!(!super::is_folded(&json!({"tags": ["a", "b"], "ids": [1]})));
+        }
+    }
 }
@@ -0,0 +1,299 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// How the csv is written: what separates, quotes and ends its fields and records
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dialect {
+    /// The byte separating fields, `,` by default
+    pub delimiter: u8,
+    /// The byte fields are quoted with, `"` by default
+    pub quote: u8,
+    /// The byte that escapes a quote inside a quoted field, such as `\`, instead of doubling it
+    pub escape: Option<u8>,
+    /// A quote inside a quoted field is written twice, `""`
+    pub double_quote: bool,
+    /// Quotes are special, otherwise they are read like any other character
+    pub quoting: bool,
+    /// Lines starting with this byte, such as `#`, are skipped up to the next `\n`
+    pub comment: Option<u8>,
+    pub trim: Trim,
+    pub terminator: Terminator,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            double_quote: true,
+            quoting: true,
+            comment: None,
+            trim: Trim::None,
+            terminator: Terminator::Crlf,
+        }
+    }
+}
+
+impl Dialect {
+    // Ragged rows are read so that they can be rejected, or padded when the reader is flexible
+    pub(crate) fn reader(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape)
+            .double_quote(self.double_quote)
+            .quoting(self.quoting)
+            .comment(self.comment)
+            .trim(self.trim.into())
+            .terminator(self.terminator.into())
+            .flexible(true);
+        builder
+    }
+
+    // Records that do not have every field, such as rejected ones, can be written too
+    pub(crate) fn writer(&self) -> csv::WriterBuilder {
+        let mut builder = csv::WriterBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .escape(self.escape.unwrap_or(b'\\'))
+            .double_quote(self.escape.is_none() && self.double_quote)
+            .flexible(true);
+        // The reader takes any line ending, which would be \r\n when writing
+        if let Terminator::Byte(byte) = self.terminator {
+            builder.terminator(csv::Terminator::Any(byte));
+        }
+        builder
+    }
+}
+
+/// Read a delimiter given as a byte, see [`parse_byte`], or one of the presets `csv`, `tsv` and
+/// `psv`
+///
+/// ```
+/// use csv2json::dialect::parse_delimiter;
+///
+/// assert_eq!(parse_delimiter("tsv"), Ok(b'\t'));
+/// assert_eq!(parse_delimiter(";"), Ok(b';'));
+/// assert!(parse_delimiter(";;").is_err());
+/// ```
+pub fn parse_delimiter(delimiter: &str) -> Result<u8, String> {
+    match delimiter {
+        "csv" => Ok(b','),
+        "tsv" => Ok(b'\t'),
+        "psv" => Ok(b'|'),
+        _ => parse_byte(delimiter),
+    }
+}
+
+/// Read a single ASCII character, which can also be given as an escape such as `\t` or `\x1f`,
+/// or by name, `tab` or `space`
+///
+/// Anything else can not be represented by the one byte the csv reader splits on, so it is an
+/// error rather than being cut down to its first byte.
+pub fn parse_byte(value: &str) -> Result<u8, String> {
+    let mut chars = value.chars();
+    let c = match value {
+        "tab" | "\\t" => '\t',
+        "space" => ' ',
+        "\\n" => '\n',
+        "\\r" => '\r',
+        "\\0" => '\0',
+        "\\\\" => '\\',
+        _ if value.starts_with("\\x") => match u8::from_str_radix(&value[2..], 16) {
+            Ok(byte) if value.len() == 4 && value[2..].chars().all(|c| c.is_ascii_hexdigit()) => {
+                byte as char
+            }
+            _ => return Err(format!("{:?} is not a valid \\x escape", value)),
+        },
+        _ => match (chars.next(), chars.next()) {
+            (Some(c), None) => c,
+            _ => return Err(format!("{:?} is not a single character", value)),
+        },
+    };
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(format!(
+            "{:?} is not an ASCII character, which is needed to read the csv",
+            value
+        ))
+    }
+}
+
+// How to write a byte so that parse_byte reads it back
+pub(crate) fn byte_name(byte: u8) -> String {
+    match byte {
+        b'\t' => String::from("tab"),
+        b' ' => String::from("space"),
+        byte if byte.is_ascii_graphic() => (byte as char).to_string(),
+        byte => format!("\\x{:02x}", byte),
+    }
+}
+
+/// Which whitespace around fields is removed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Trim {
+    #[default]
+    None,
+    Headers,
+    Fields,
+    All,
+}
+
+impl FromStr for Trim {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Trim::None),
+            "headers" => Ok(Trim::Headers),
+            "fields" => Ok(Trim::Fields),
+            "all" => Ok(Trim::All),
+            _ => Err(format!("Unknown trim: {}", s)),
+        }
+    }
+}
+
+impl From<Trim> for csv::Trim {
+    fn from(trim: Trim) -> Self {
+        match trim {
+            Trim::None => csv::Trim::None,
+            Trim::Headers => csv::Trim::Headers,
+            Trim::Fields => csv::Trim::Fields,
+            Trim::All => csv::Trim::All,
+        }
+    }
+}
+
+/// What ends each record
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Terminator {
+    /// Any of `\r\n`, `\n` or `\r`
+    #[default]
+    Crlf,
+    Byte(u8),
+}
+
+impl FromStr for Terminator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "crlf" => Ok(Terminator::Crlf),
+            "cr" => Ok(Terminator::Byte(b'\r')),
+            "lf" => Ok(Terminator::Byte(b'\n')),
+            _ => parse_byte(s).map(Terminator::Byte),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Crlf => write!(f, "crlf"),
+            Terminator::Byte(b'\r') => write!(f, "cr"),
+            Terminator::Byte(b'\n') => write!(f, "lf"),
+            Terminator::Byte(byte) => write!(f, "{}", byte_name(*byte)),
+        }
+    }
+}
+
+impl From<Terminator> for csv::Terminator {
+    fn from(terminator: Terminator) -> Self {
+        match terminator {
+            Terminator::Crlf => csv::Terminator::CRLF,
+            Terminator::Byte(byte) => csv::Terminator::Any(byte),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(dialect: Dialect, csv: &str) -> Vec<Vec<String>> {
+        dialect
+            .reader()
+            .has_headers(false)
+            .from_reader(csv.as_bytes())
+            .records()
+            .map(|record| record.unwrap().iter().map(String::from).collect())
+            .collect()
+    }
+
+    #[test]
+    fn it_reads_csv_in_the_dialect() {
+        let dialect = Dialect {
+            delimiter: b';',
+            quote: b'\'',
+            escape: Some(b'\\'),
+            comment: Some(b'#'),
+            trim: Trim::All,
+            ..Default::default()
+        };
+        assert_eq!(
+            read(dialect, "# exported\n a ;'b;\\'c'\n"),
+            vec![vec!["a", "b;'c"]]
+        );
+    }
+
+    #[test]
+    fn it_reads_records_ending_in_the_terminator() {
+        let dialect = Dialect {
+            terminator: "cr".parse().unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            read(dialect, "a,b\rc,\"d\ne\"\r"),
+            vec![vec!["a", "b"], vec!["c", "d\ne"]]
+        );
+    }
+
+    #[test]
+    fn it_reads_quotes_as_text_without_quoting() {
+        let dialect = Dialect {
+            quoting: false,
+            ..Default::default()
+        };
+        assert_eq!(read(dialect, "\"a,b\"\n"), vec![vec!["\"a", "b\""]]);
+    }
+
+    #[test]
+    fn it_parses_terminators() {
+        assert_eq!("crlf".parse(), Ok(Terminator::Crlf));
+        assert_eq!("lf".parse(), Ok(Terminator::Byte(b'\n')));
+        assert_eq!(";".parse(), Ok(Terminator::Byte(b';')));
+        assert_eq!("\\x1e".parse(), Ok(Terminator::Byte(0x1e)));
+        assert!("\r\n".parse::<Terminator>().is_err());
+    }
+
+    #[test]
+    fn it_parses_escaped_and_named_bytes() {
+        assert_eq!(parse_byte("\\t"), Ok(b'\t'));
+        assert_eq!(parse_byte("tab"), Ok(b'\t'));
+        assert_eq!(parse_byte("\\x1f"), Ok(0x1f));
+        assert_eq!(parse_byte("\\"), Ok(b'\\'));
+        assert_eq!(parse_byte("\\\\"), Ok(b'\\'));
+        assert_eq!(parse_delimiter("psv"), Ok(b'|'));
+    }
+
+    #[test]
+    fn it_rejects_what_is_not_a_single_byte() {
+        assert_eq!(
+            parse_byte(";;"),
+            Err(String::from(r#"";;" is not a single character"#))
+        );
+        assert_eq!(
+            parse_byte("¦"),
+            Err(String::from(
+                r#""¦" is not an ASCII character, which is needed to read the csv"#
+            ))
+        );
+        assert!(parse_byte("\\xff").is_err());
+        assert!(parse_byte("\\xzz").is_err());
+        assert!(parse_byte("").is_err());
+    }
+}
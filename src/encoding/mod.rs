@@ -0,0 +1,319 @@
+//! Reads csv in other character encodings as UTF-8
+//!
+//! Excel on Windows saves csv as Windows-1252, or as UTF-16 with a byte order mark. The encoding
+//! can be given or worked out from the byte order mark, or failing that from the start of the csv.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::str::FromStr;
+
+/// How many bytes from the start of the csv are used to detect its encoding
+pub const SAMPLE_SIZE: usize = 16 * 1024;
+
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// A character encoding the csv can be in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// Also used for ISO-8859-1, which it is a superset of apart from control characters
+    Windows1252,
+}
+
+impl Encoding {
+    /// The encoding of the byte order mark the bytes start with, and the length of the mark
+    pub fn from_bom(bytes: &[u8]) -> Option<(Encoding, usize)> {
+        if bytes.starts_with(b"\xEF\xBB\xBF") {
+            Some((Encoding::Utf8, 3))
+        } else if bytes.starts_with(b"\xFF\xFE") {
+            Some((Encoding::Utf16Le, 2))
+        } else if bytes.starts_with(b"\xFE\xFF") {
+            Some((Encoding::Utf16Be, 2))
+        } else {
+            None
+        }
+    }
+
+    /// Work out the encoding of the bytes from their byte order mark, or failing that by guessing
+    ///
+    /// Mostly ASCII text in UTF-16 has a zero in every other byte. Anything else is UTF-8 if it is
+    /// valid UTF-8, apart from a character cut short at the end, otherwise it is Windows-1252.
+    ///
+    /// ```
+    /// use csv2json::encoding::Encoding;
+    ///
+    /// assert_eq!(Encoding::detect("café".as_bytes()), Encoding::Utf8);
+    /// assert_eq!(Encoding::detect(b"caf\xE9 noir"), Encoding::Windows1252);
+    /// assert_eq!(Encoding::detect(b"c\0a\0f\0\xE9\0"), Encoding::Utf16Le);
+    /// ```
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        if let Some((encoding, _)) = Encoding::from_bom(bytes) {
+            return encoding;
+        }
+        let zeros = |start| {
+            bytes
+                .iter()
+                .skip(start)
+                .step_by(2)
+                .filter(|&&b| b == 0)
+                .count()
+        };
+        let (even, odd) = (zeros(0), zeros(1));
+        let pairs = bytes.len() / 2;
+        if pairs > 0 && odd * 2 > pairs && even * 4 < odd {
+            return Encoding::Utf16Le;
+        }
+        if pairs > 0 && even * 2 > pairs && odd * 4 < even {
+            return Encoding::Utf16Be;
+        }
+        match ::std::str::from_utf8(bytes) {
+            Ok(_) => Encoding::Utf8,
+            Err(err) if err.error_len().is_none() => Encoding::Utf8,
+            Err(_) => Encoding::Windows1252,
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Ok(Encoding::Utf8),
+            "utf-16le" | "utf16le" => Ok(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Encoding::Utf16Be),
+            "windows-1252" | "cp1252" | "iso-8859-1" | "latin1" | "latin-1" => {
+                Ok(Encoding::Windows1252)
+            }
+            _ => Err(format!("Unknown encoding: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Windows1252 => "windows-1252",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The characters Windows-1252 has in 0x80 to 0x9F, the rest of its bytes are the same as in
+// Unicode. The five bytes it leaves undefined are read as the control characters they are in
+// ISO-8859-1.
+const WINDOWS_1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8D}', 'Ž', '\u{8F}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9D}', 'ž', 'Ÿ',
+];
+
+/// Reads csv in any [`Encoding`] as UTF-8, without its byte order mark
+///
+/// ```
+/// use csv2json::encoding::{Decoder, Encoding};
+/// use std::io::Read;
+///
+/// let mut decoder = Decoder::new(&b"\xFF\xFEn\0a\0m\0e\0"[..], None).unwrap();
+/// let mut csv = String::new();
+/// decoder.read_to_string(&mut csv).unwrap();
+/// assert_eq!(decoder.encoding(), Encoding::Utf16Le);
+/// assert_eq!(csv, "name");
+/// ```
+#[derive(Debug)]
+pub struct Decoder<R> {
+    reader: R,
+    encoding: Encoding,
+    // Bytes read but not yet decoded, such as half of a UTF-16 character
+    input: Vec<u8>,
+    output: Vec<u8>,
+    // How much of the output has been read
+    position: usize,
+    eof: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    /// Decode the csv in `reader` from `encoding`, or detect it with [`Encoding::detect`] from
+    /// the start of the csv if it is `None`
+    ///
+    /// A byte order mark wins over the encoding that was given, as a csv saved in one encoding
+    /// would not start with the mark of another, and it is removed. Check
+    /// [`Decoder::encoding`] to see whether the encoding was changed.
+    pub fn new(mut reader: R, encoding: Option<Encoding>) -> io::Result<Self> {
+        let mut input = Vec::new();
+        let size = if encoding.is_some() { 3 } else { SAMPLE_SIZE };
+        reader.by_ref().take(size as u64).read_to_end(&mut input)?;
+        let encoding = match Encoding::from_bom(&input) {
+            Some((bom, length)) => {
+                input.drain(..length);
+                bom
+            }
+            None => encoding.unwrap_or_else(|| Encoding::detect(&input)),
+        };
+        Ok(Decoder {
+            reader,
+            encoding,
+            input,
+            output: Vec::new(),
+            position: 0,
+            eof: false,
+        })
+    }
+
+    /// The encoding the csv is read as, which was given, detected or taken from its byte order
+    /// mark
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    // Decode as much of the input as can be, leaving anything cut short unless it is the end
+    fn decode(&mut self) {
+        self.output.clear();
+        self.position = 0;
+        let decoded = match self.encoding {
+            Encoding::Utf8 => {
+                self.output.append(&mut self.input);
+                return;
+            }
+            Encoding::Windows1252 => {
+                let text: String = self.input.iter().map(|&b| windows_1252(b)).collect();
+                self.output.extend_from_slice(text.as_bytes());
+                self.input.len()
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = self.input.chunks_exact(2).map(|pair| match self.encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                });
+                let mut units: Vec<u16> = units.collect();
+                // The other half of a surrogate pair is still to come
+                let cut_short = units
+                    .last()
+                    .is_some_and(|&unit| (0xD800..0xDC00).contains(&unit));
+                if cut_short && !self.eof {
+                    units.pop();
+                }
+                let text: String = ::std::char::decode_utf16(units.iter().cloned())
+                    .map(|c| c.unwrap_or(::std::char::REPLACEMENT_CHARACTER))
+                    .collect();
+                self.output.extend_from_slice(text.as_bytes());
+                if self.eof {
+                    if self.input.len() % 2 == 1 {
+                        self.output.extend_from_slice("\u{FFFD}".as_bytes());
+                    }
+                    self.input.len()
+                } else {
+                    units.len() * 2
+                }
+            }
+        };
+        self.input.drain(..decoded);
+    }
+}
+
+fn windows_1252(byte: u8) -> char {
+    match byte {
+        0x80..=0x9F => WINDOWS_1252[(byte - 0x80) as usize],
+        byte => byte as char,
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.eof && self.input.is_empty() {
+                return Ok(0);
+            }
+            if !self.eof {
+                let mut chunk = [0; CHUNK_SIZE];
+                let read = self.reader.read(&mut chunk)?;
+                self.input.extend_from_slice(&chunk[..read]);
+                self.eof = read == 0;
+            }
+            self.decode();
+        }
+        let read = buf.len().min(self.output.len() - self.position);
+        buf[..read].copy_from_slice(&self.output[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8], encoding: Option<Encoding>) -> String {
+        let mut text = String::new();
+        Decoder::new(bytes, encoding)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn it_decodes_windows_1252() {
+        let csv = b"name,price\nCaf\xE9,\x8010\n";
+        assert_eq!(
+            decode(csv, Some(Encoding::Windows1252)),
+            "name,price\nCafé,€10\n"
+        );
+        assert_eq!(decode(csv, None), "name,price\nCafé,€10\n");
+    }
+
+    #[test]
+    fn it_decodes_utf_16_across_reads() {
+        // Reads end in the middle of characters and surrogate pairs
+        let text = "name,city\n".to_owned() + &"Zoë,Köln 😀\n".repeat(CHUNK_SIZE);
+        let mut le = vec![0xFF, 0xFE];
+        let mut be = vec![];
+        for unit in text.encode_utf16() {
+            le.extend_from_slice(&unit.to_le_bytes());
+            be.extend_from_slice(&unit.to_be_bytes());
+        }
+        assert_eq!(decode(&le, None), text);
+        assert_eq!(decode(&be, None), text);
+        assert_eq!(decode(&be, Some(Encoding::Utf16Be)), text);
+    }
+
+    #[test]
+    fn it_removes_the_byte_order_mark() {
+        assert_eq!(decode(b"\xEF\xBB\xBFname", None), "name");
+        assert_eq!(decode(b"\xEF\xBB\xBFname", Some(Encoding::Utf8)), "name");
+    }
+
+    #[test]
+    fn it_follows_the_byte_order_mark_over_the_encoding_given() {
+        let decoder = Decoder::new(&b"\xFF\xFEn\0a\0m\0e\0"[..], Some(Encoding::Utf8)).unwrap();
+        assert_eq!(decoder.encoding(), Encoding::Utf16Le);
+        assert_eq!(
+            decode(b"\xFE\xFF\0n\0a\0m\0e", Some(Encoding::Windows1252)),
+            "name"
+        );
+    }
+
+    #[test]
+    fn it_replaces_what_can_not_be_decoded() {
+        assert_eq!(
+            decode(&[b'a', 0, 0x3D, 0xD8, b'b'], Some(Encoding::Utf16Le)),
+            "a\u{FFFD}\u{FFFD}"
+        );
+    }
+
+    #[test]
+    fn it_parses_encoding_names() {
+        assert_eq!("UTF-16LE".parse(), Ok(Encoding::Utf16Le));
+        assert_eq!("latin1".parse(), Ok(Encoding::Windows1252));
+        assert_eq!(
+            "cp1252".parse::<Encoding>().unwrap().to_string(),
+            "windows-1252"
+        );
+        assert!("ebcdic".parse::<Encoding>().is_err());
+    }
+}
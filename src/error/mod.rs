@@ -0,0 +1,150 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+use std::result::Result as StdResult;
+use std::str::FromStr;
+
+pub type Result<T> = StdResult<T, Error>;
+
+/// Everything that can go wrong while converting a csv into json
+#[derive(Debug)]
+pub enum Error {
+    /// The csv could not be read or parsed
+    Csv(csv::Error),
+    /// Reading input or writing output failed
+    Io(io::Error),
+    /// The json output could not be serialized
+    Json(serde_json::Error),
+    /// The json input could not be read, when converting json back into csv
+    JsonInput(serde_json::Error),
+    /// A record could not be converted and the policy is to fail
+    Rejected(Rejection),
+    /// The schema could not be read or the csv does not match it
+    Schema(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Csv(err) => write!(f, "Could not read csv: {}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Json(err) => write!(f, "Could not write json: {}", err),
+            Error::JsonInput(err) => write!(f, "Could not read json: {}", err),
+            Error::Rejected(rejection) => write!(f, "Could not convert {}", rejection),
+            Error::Schema(err) => write!(f, "Schema error: {}", err),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Csv(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::Json(err) | Error::JsonInput(err) => Some(err),
+            Error::Rejected(_) | Error::Schema(_) => None,
+        }
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Csv(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+/// A csv record that could not be converted, and why
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rejection {
+    /// The line the record starts on, counting from 1
+    pub line: u64,
+    /// The byte offset the record starts at
+    pub byte: u64,
+    pub reason: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "record on line {} (byte {}): {}",
+            self.line, self.byte, self.reason
+        )
+    }
+}
+
+/// Something that did not stop the conversion but is probably not what was intended
+///
+/// These are passed to [`Rows::on_warning`](::Rows::on_warning) as they happen.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    /// A record was dropped because of [`ErrorPolicy::Warn`]
+    Skipped(Rejection),
+    /// A selector, such as a numeric column, does not match any of the headers
+    NoColumnsMatching(String),
+    /// The object at this JSON Pointer has both numeric and non-numeric keys, so it is not made
+    /// into an array
+    MixedKeys(String),
+    /// The object at this JSON Pointer has keys past [`MAX_POSITION`](::data::MAX_POSITION), so
+    /// it is not made into a positional array
+    PositionsOutOfRange(String),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::Skipped(rejection) => write!(f, "Skipping {}", rejection),
+            Warning::NoColumnsMatching(selector) => {
+                write!(f, "there are no columns matching {}", selector)
+            }
+            Warning::MixedKeys(pointer) => write!(
+                f,
+                "{} has both numeric and non-numeric keys so it will not be an array",
+                pointer
+            ),
+            Warning::PositionsOutOfRange(pointer) => write!(
+                f,
+                "{} has positions past {} so it will not be an array",
+                pointer,
+                ::data::MAX_POSITION
+            ),
+        }
+    }
+}
+
+/// What to do with records that can not be converted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Drop the record without saying anything
+    Skip,
+    /// Drop the record and report it as a [`Warning::Skipped`]
+    #[default]
+    Warn,
+    /// Stop converting with an [`Error::Rejected`]
+    Fail,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        match s {
+            "skip" => Ok(ErrorPolicy::Skip),
+            "warn" => Ok(ErrorPolicy::Warn),
+            "fail" => Ok(ErrorPolicy::Fail),
+            _ => Err(format!("Unknown error policy: {}", s)),
+        }
+    }
+}
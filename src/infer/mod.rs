@@ -0,0 +1,252 @@
+use data;
+use schema::{ColumnSchema, Schema};
+use serde_json::Value;
+use std::collections::HashSet;
+use types::{ColumnType, NumberFormat, TypeSettings};
+
+// How many example values are kept for each column
+const EXAMPLES: usize = 3;
+
+// What each column could still be, a type is ruled out as soon as one value does not fit.
+#[derive(Clone, Debug)]
+struct Candidates {
+    integer: bool,
+    number: bool,
+    boolean: bool,
+    // Whether any non-empty values were seen at all
+    values: bool,
+    // Whether any empty values were seen
+    empty: bool,
+    distinct: HashSet<String>,
+    // The first few distinct values, in the order they were seen
+    examples: Vec<String>,
+}
+
+impl Candidates {
+    fn new() -> Self {
+        Candidates {
+            integer: true,
+            number: true,
+            boolean: true,
+            values: false,
+            empty: false,
+            distinct: HashSet::new(),
+            examples: vec![],
+        }
+    }
+
+    fn add(&mut self, value: &str, format: &NumberFormat) {
+        if value.is_empty() {
+            self.empty = true;
+            return;
+        }
+        self.values = true;
+        if !self.distinct.contains(value) {
+            self.distinct.insert(value.to_owned());
+            if self.examples.len() < EXAMPLES {
+                self.examples.push(value.to_owned());
+            }
+        }
+        // Numbers are read the same way they are when converting, in the given number format
+        if self.integer {
+            self.integer = data::string_to_typed_number(value, ColumnType::Integer, format).is_ok();
+        }
+        if self.number {
+            self.number = data::string_to_typed_number(value, ColumnType::Float, format).is_ok();
+        }
+        if self.boolean {
+            let value = value.to_lowercase();
+            self.boolean = value == "true" || value == "false";
+        }
+    }
+
+    // Pick the most specific type every value fitted
+    fn column_type(&self) -> ColumnType {
+        if !self.values {
+            ColumnType::Null
+        } else if self.integer {
+            ColumnType::Integer
+        } else if self.number {
+            ColumnType::Float
+        } else if self.boolean {
+            ColumnType::Boolean
+        } else {
+            ColumnType::String
+        }
+    }
+
+    fn column_schema(&self, name: &str, settings: &TypeSettings) -> ColumnSchema {
+        let column_type = self.column_type();
+        let examples = self
+            .examples
+            .iter()
+            .map(|example| {
+                let column = ColumnSchema::new(name, column_type);
+                data::string_to_value(example, &column, settings)
+                    .unwrap_or_else(|_| Value::String(example.clone()))
+            })
+            .collect();
+        ColumnSchema {
+            nullable: self.empty,
+            cardinality: Some(self.distinct.len()),
+            examples,
+            ..ColumnSchema::new(name, column_type)
+        }
+    }
+}
+
+/// Works out the type of each column from the records it is given
+///
+/// A column is only given a type if every non-empty value fits it, otherwise it is left as a
+/// string. Numbers are read in the number format of the settings, as they are when converting.
+/// Columns that only contain empty values are null.
+#[derive(Clone, Debug)]
+pub struct TypeInference {
+    headers: csv::StringRecord,
+    settings: TypeSettings,
+    columns: Vec<Candidates>,
+}
+
+impl TypeInference {
+    pub fn new(headers: &csv::StringRecord, settings: &TypeSettings) -> Self {
+        TypeInference {
+            headers: headers.clone(),
+            settings: settings.clone(),
+            columns: vec![Candidates::new(); headers.len()],
+        }
+    }
+
+    pub fn add(&mut self, record: &csv::StringRecord) {
+        let format = &self.settings.numbers;
+        self.columns
+            .iter_mut()
+            .zip(record.iter())
+            .for_each(|(candidates, value)| candidates.add(value, format));
+    }
+
+    /// The type of each column, in the same order as the headers
+    pub fn column_types(&self) -> Vec<(String, ColumnType)> {
+        self.headers
+            .iter()
+            .zip(self.columns.iter())
+            .map(|(header, candidates)| (header.to_owned(), candidates.column_type()))
+            .collect()
+    }
+
+    /// A schema describing every column: its type, whether it had empty values, how many distinct
+    /// values it had and a few examples of them
+    pub fn schema(&self) -> Schema {
+        Schema {
+            columns: self
+                .headers
+                .iter()
+                .zip(self.columns.iter())
+                .map(|(header, candidates)| candidates.column_schema(header, &self.settings))
+                .collect(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(rows: &[&[&str]]) -> Vec<ColumnType> {
+        infer_with(rows, &TypeSettings::default())
+    }
+
+    fn infer_with(rows: &[&[&str]], settings: &TypeSettings) -> Vec<ColumnType> {
+        let mut inference =
+            TypeInference::new(&csv::StringRecord::from(rows[0].to_vec()), settings);
+        rows[1..]
+            .iter()
+            .for_each(|row| inference.add(&csv::StringRecord::from(row.to_vec())));
+        inference
+            .column_types()
+            .into_iter()
+            .map(|(_, column_type)| column_type)
+            .collect()
+    }
+
+    #[test]
+    fn it_infers_each_type() {
+        assert_eq!(
+            infer(&[
+                &["int", "num", "bool", "null", "str"],
+                &["1", "1.5", "true", "", "a"],
+                &["-2", "2", "FALSE", "", "1"],
+            ]),
+            vec![
+                ColumnType::Integer,
+                ColumnType::Float,
+                ColumnType::Boolean,
+                ColumnType::Null,
+                ColumnType::String
+            ]
+        );
+    }
+
+    #[test]
+    fn it_ignores_empty_values() {
+        assert_eq!(
+            infer(&[&["int"], &["1"], &[""], &["3"]]),
+            vec![ColumnType::Integer]
+        );
+    }
+
+    #[test]
+    fn it_only_promotes_columns_where_every_value_fits() {
+        assert_eq!(
+            infer(&[&["mixed"], &["1"], &["n/a"], &["3"]]),
+            vec![ColumnType::String]
+        );
+    }
+
+    #[test]
+    fn it_reads_numbers_in_the_number_format() {
+        let rows: &[&[&str]] = &[&["int", "num"], &["1.234", "1,5"], &["5", "-2,25"]];
+        assert_eq!(infer(rows), vec![ColumnType::Float, ColumnType::String]);
+        let german = TypeSettings {
+            numbers: NumberFormat::from_locale("de").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            infer_with(rows, &german),
+            vec![ColumnType::Integer, ColumnType::Float]
+        );
+    }
+
+    #[test]
+    fn it_describes_each_column() {
+        let mut inference = TypeInference::new(
+            &csv::StringRecord::from(vec!["id", "colour"]),
+            &TypeSettings::default(),
+        );
+        for row in &[
+            ["1", "red"],
+            ["2", ""],
+            ["3", "blue"],
+            ["4", "red"],
+            ["5", "green"],
+        ] {
+            inference.add(&csv::StringRecord::from(row.to_vec()));
+        }
+        let schema = inference.schema();
+
+        let id = &schema.columns[0];
+        assert_eq!(id.column_type, ColumnType::Integer);
+        assert!(!id.nullable);
+        assert_eq!(id.cardinality, Some(5));
+        assert_eq!(id.examples, vec![json!(1), json!(2), json!(3)]);
+
+        let colour = &schema.columns[1];
+        assert_eq!(colour.column_type, ColumnType::String);
+        assert!(colour.nullable);
+        assert_eq!(colour.cardinality, Some(3));
+        assert_eq!(
+            colour.examples,
+            vec![json!("red"), json!("blue"), json!("green")]
+        );
+    }
+}
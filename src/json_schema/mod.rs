@@ -0,0 +1,236 @@
+// Describes the json produced by a conversion as a JSON Schema.
+//
+// The shape comes from a template row, where each value is the index of the column that would
+// be there. Anything else in the template is a gap, which is always null.
+
+use schema::ColumnSchema;
+use serde_json::{Map, Value};
+use types::{ColumnType, DateOutput, DateSettings, TypeErrorPolicy, TypeSettings};
+
+const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+pub fn add_dialect(schema: Value) -> Value {
+    match schema {
+        Value::Object(object) => {
+            let mut with_dialect = Map::new();
+            with_dialect.insert(String::from("$schema"), json!(DIALECT));
+            with_dialect.extend(object);
+            Value::Object(with_dialect)
+        }
+        schema => schema,
+    }
+}
+
+// The schema for each row from a schema for an array of rows, such as the one written for the
+// output as a whole, keeping what the items can refer to. Anything else is already for a row.
+pub fn row_schema(schema: &Value) -> Value {
+    let object = match schema.as_object() {
+        Some(object) if object.get("type") == Some(&json!("array")) => object,
+        _ => return schema.clone(),
+    };
+    let mut row = match object.get("items") {
+        Some(Value::Object(items)) => items.clone(),
+        _ => return schema.clone(),
+    };
+    for &key in &["$schema", "$defs", "definitions"] {
+        if let Some(value) = object.get(key) {
+            row.entry(key).or_insert_with(|| value.clone());
+        }
+    }
+    Value::Object(row)
+}
+
+// The values a column can have, including what its empty values are replaced with
+pub fn column(column: Option<&ColumnSchema>, settings: &TypeSettings) -> Value {
+    let mut schema = match column {
+        Some(column) => column_type(column.column_type, &settings.dates),
+        None => column_type(ColumnType::String, &settings.dates),
+    };
+    // Any value could be one of the null values
+    if !settings.null_values.is_empty() {
+        schema = nullable(schema);
+    }
+    let column = match column {
+        Some(column) => column,
+        None => return schema,
+    };
+    if column.column_type == ColumnType::Boolean && settings.booleans.empty_is_null {
+        schema = nullable(schema);
+    }
+    schema = match column.on_type_error.unwrap_or(settings.on_type_error) {
+        TypeErrorPolicy::Fail => schema,
+        TypeErrorPolicy::Null => nullable(schema),
+        TypeErrorPolicy::Keep if allows(&schema, &json!("")) => schema,
+        TypeErrorPolicy::Keep => either(schema, json!({"type": "string"})),
+        TypeErrorPolicy::Default => {
+            let default = column
+                .default
+                .as_ref()
+                .unwrap_or(&settings.type_error_default);
+            or_value(schema, default)
+        }
+    };
+    match column.empty_value() {
+        None => schema,
+        Some(ref value) => or_value(schema, value),
+    }
+}
+
+// Widen the schema so that it also allows the value
+fn or_value(schema: Value, value: &Value) -> Value {
+    match value {
+        Value::Null => nullable(schema),
+        value if allows(&schema, value) => schema,
+        value => either(schema, json!({ "const": value })),
+    }
+}
+
+// A schema allowing the values of both schemas
+fn either(schema: Value, other: Value) -> Value {
+    match schema {
+        Value::Object(mut object) if object.contains_key("anyOf") => {
+            if let Some(Value::Array(schemas)) = object.get_mut("anyOf") {
+                schemas.push(other);
+            }
+            Value::Object(object)
+        }
+        schema => json!({ "anyOf": [schema, other] }),
+    }
+}
+
+fn column_type(column_type: ColumnType, dates: &DateSettings) -> Value {
+    match column_type {
+        ColumnType::String => json!({"type": "string"}),
+        ColumnType::Integer => json!({"type": "integer"}),
+        ColumnType::Float | ColumnType::Number | ColumnType::Decimal => json!({"type": "number"}),
+        ColumnType::Boolean => json!({"type": "boolean"}),
+        ColumnType::Date | ColumnType::Datetime if dates.output != DateOutput::Iso => {
+            json!({"type": "integer"})
+        }
+        ColumnType::Date => json!({"type": "string", "format": "date"}),
+        // Datetimes without a timezone have no offset, which the date-time format requires
+        ColumnType::Datetime if dates.timezone.is_some() => {
+            json!({"type": "string", "format": "date-time"})
+        }
+        ColumnType::Datetime => json!({"type": "string"}),
+        ColumnType::Json => json!({}),
+        ColumnType::Null => json!({"type": "null"}),
+    }
+}
+
+fn nullable(mut schema: Value) -> Value {
+    if let Some(Value::String(name)) = schema.get("type").cloned() {
+        if name != "null" {
+            schema["type"] = json!([name, "null"]);
+        }
+    } else if schema.get("anyOf").is_some() && !allows(&schema, &Value::Null) {
+        schema = either(schema, json!({"type": "null"}));
+    }
+    schema
+}
+
+// Whether the type of the schema includes the value
+fn allows(schema: &Value, value: &Value) -> bool {
+    if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+        return schemas.iter().any(|schema| allows(schema, value));
+    }
+    if let Some(constant) = schema.get("const") {
+        return constant == value;
+    }
+    let name = match schema.get("type") {
+        Some(Value::String(name)) => name.as_str(),
+        Some(Value::Array(names)) => {
+            return names
+                .iter()
+                .any(|name| allows(&json!({ "type": name }), value))
+        }
+        _ => return true,
+    };
+    match value {
+        Value::Null => name == "null",
+        Value::Bool(_) => name == "boolean",
+        Value::Number(number) => name == "number" || (name == "integer" && !number.is_f64()),
+        Value::String(_) => name == "string",
+        Value::Array(_) => name == "array",
+        Value::Object(_) => name == "object",
+    }
+}
+
+// Describe a template row. When empty values are removed any key could be missing.
+pub fn describe(template: &Value, columns: &[Value], optional: bool) -> Value {
+    match template {
+        Value::Number(index) => index
+            .as_u64()
+            .and_then(|index| columns.get(index as usize))
+            .cloned()
+            .unwrap_or_else(|| json!({})),
+        Value::Object(object) => {
+            let properties: Map<String, Value> = object
+                .iter()
+                .map(|(key, value)| (key.clone(), describe(value, columns, optional)))
+                .collect();
+            let mut schema = json!({
+                "type": "object",
+                "properties": properties,
+                "additionalProperties": false,
+            });
+            if !optional {
+                schema["required"] = json!(object.keys().collect::<Vec<_>>());
+            }
+            schema
+        }
+        Value::Array(items) => json!({
+            "type": "array",
+            "items": any_of(items.iter().map(|item| describe(item, columns, optional)).collect()),
+        }),
+        _ => json!({"type": "null"}),
+    }
+}
+
+// Describe a folded template, an object with an array holding the template's value for each key
+pub fn describe_folded(template: &Value, columns: &[Value], optional: bool) -> Value {
+    let object = match template {
+        Value::Object(object) => object,
+        _ => return describe(template, columns, optional),
+    };
+    let properties: Map<String, Value> = object
+        .iter()
+        .map(|(key, value)| {
+            // Every folded array has one item per row, which is null if the row has no value
+            let mut items = match value {
+                Value::Array(items) => any_of(
+                    items
+                        .iter()
+                        .map(|item| describe(item, columns, optional))
+                        .collect(),
+                ),
+                _ => describe(value, columns, optional),
+            };
+            if optional {
+                items = any_of(vec![items, json!({"type": "null"})]);
+            }
+            (key.clone(), json!({"type": "array", "items": items}))
+        })
+        .collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": object.keys().collect::<Vec<_>>(),
+        "additionalProperties": false,
+    })
+}
+
+// A schema matching any of the given schemas, without repeating any
+fn any_of(schemas: Vec<Value>) -> Value {
+    let mut distinct: Vec<Value> = Vec::new();
+    for schema in schemas {
+        if !distinct.contains(&schema) {
+            distinct.push(schema);
+        }
+    }
+    match distinct.len() {
+        0 => json!({}),
+        1 => distinct.pop().unwrap(),
+        _ => json!({ "anyOf": distinct }),
+    }
+}
@@ -0,0 +1,42 @@
+//! Converts CSVs into JSON documents
+//!
+//! This is the library behind the `csv2json` command line tool. Build a [`Converter`] from
+//! [`ConvertOptions`], which has a method for each command line option, then use it to convert
+//! any reader into a [`serde_json::Value`] or to write the json straight out to any writer.
+//!
+//! ```
+//! use csv2json::ConvertOptions;
+//!
+//! let converter = ConvertOptions::new().fold(true).build();
+//! let json = converter.convert("foo,bar\n1,a\n2,b\n".as_bytes()).unwrap();
+//! assert_eq!(json["foo"][1], "2");
+//! ```
+
+extern crate chrono;
+extern crate csv;
+extern crate jsonschema;
+extern crate regex;
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate serde_json;
+extern crate toml;
+
+mod convert;
+pub mod data;
+pub mod dialect;
+pub mod encoding;
+mod error;
+pub mod infer;
+mod json_schema;
+pub mod schema;
+mod selector;
+pub mod sniff;
+pub mod types;
+
+pub use convert::{ConvertOptions, Converter, Row, Rows, Summary};
+pub use dialect::{Dialect, Terminator, Trim};
+pub use error::{Error, ErrorPolicy, Rejection, Result, Warning};
+pub use schema::{ColumnSchema, Schema};
+pub use selector::Selector;
+pub use types::{BooleanFormat, ColumnType, DateOutput, NumberFormat};
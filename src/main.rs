@@ -1,143 +1,384 @@
-extern crate clap;
-extern crate csv;
-extern crate serde;
 #[macro_use]
+extern crate clap;
+extern crate csv2json;
 extern crate serde_json;
 extern crate strfmt;
 
 mod cli;
-mod data;
 mod sys;
 
-use serde_json::Map;
-use serde_json::Value;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use clap::ArgMatches;
+use csv2json::data::ArrayMode;
+use csv2json::dialect::{parse_byte, parse_delimiter};
+use csv2json::encoding::Decoder;
+use csv2json::sniff::{sniff_reader, Known};
+use csv2json::types::{parse_timezone, BooleanFormat, NumberFormat, TypeErrorPolicy};
+use csv2json::{
+    ConvertOptions, DateOutput, Dialect, ErrorPolicy, Schema, Selector, Summary, Terminator, Trim,
+    Warning,
+};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::process;
 use strfmt::strfmt;
 
 fn main() {
+    if let Err(err) = run() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+fn run() -> csv2json::Result<()> {
     let cli_matches = cli::get_matches();
+    if let Some(matches) = cli_matches.subcommand_matches(cli::INFER_SCHEMA) {
+        return infer_schema(matches);
+    }
+    if let Some(matches) = cli_matches.subcommand_matches(cli::JSON2CSV) {
+        return json_to_csv(matches);
+    }
 
     let csv_file = cli_matches.value_of(cli::IN);
     let out_dir = cli_matches.value_of(cli::OUT_DIR);
     let out_name = cli_matches.value_of(cli::OUT_NAME);
-    let delimiter = cli_matches.value_of(cli::DELIMITER).unwrap(); // Has a default
-    let delimiter_byte = *delimiter.as_bytes().first().expect("No delimiter provided");
-    let ds = cli_matches.value_of(cli::DIMENSIONAL_SEPARATOR);
-    let na = cli_matches.is_present(cli::ARRAYS);
-    let res = cli_matches.is_present(cli::REMOVE_EMPTY_STRINGS);
-    let reo = cli_matches.is_present(cli::REMOVE_EMPTY_OBJECTS);
-    let boolean_columns = cli_matches
-        .values_of_lossy(cli::BOOLEAN)
-        .unwrap_or_else(|| vec![]);
-    let numeric_columns = cli_matches
-        .values_of_lossy(cli::NUMERIC)
-        .unwrap_or_else(|| vec![]);
-    let fold = cli_matches.is_present(cli::FOLD);
-    let jsonl = cli_matches.is_present(cli::JSONL);
-    let reader: Box<dyn Read> = match csv_file {
-        Some(csv_file) => {
-            let file = File::open(csv_file).expect("Could not read csv file");
-            Box::new(file)
-        },
-        None => {
-            eprintln!("Reading from standard input, press Ctrl+D or Ctrl+C to exit.");
-            eprintln!("Use --in if you meant to specify a csv file.");
-            eprintln!("Use --help for usage information.");
-            Box::new(std::io::stdin())
-        }
+    let boolean_columns = selectors(&cli_matches, cli::BOOLEAN, cli::BOOLEAN_REGEX);
+    let numeric_columns = selectors(&cli_matches, cli::NUMERIC, cli::NUMERIC_REGEX);
+    let integer_columns = selectors(&cli_matches, cli::INTEGER, cli::INTEGER_REGEX);
+    let float_columns = selectors(&cli_matches, cli::FLOAT, cli::FLOAT_REGEX);
+    let decimal_columns = selectors(&cli_matches, cli::DECIMAL, cli::DECIMAL_REGEX);
+    let null_values = cli_matches
+        .values_of_lossy(cli::NULL_VALUES)
+        .unwrap_or_default();
+    let nullable_columns = cli_matches
+        .values_of_lossy(cli::NULLABLE)
+        .unwrap_or_default();
+    let positional_arrays = cli_matches.is_present(cli::POSITIONAL_ARRAYS);
+    let array_mode = if positional_arrays {
+        ArrayMode::Positional
+    } else {
+        ArrayMode::Ordered
     };
-    let mut csv_reader = csv::ReaderBuilder::new()
-        .delimiter(delimiter_byte)
-        .from_reader(reader);
-
-    let raw_rows: Vec<HashMap<String, String>> = csv_reader
-        .deserialize()
-        .filter(|result| result.is_ok())
-        .map(|result| -> HashMap<String, String> { result.unwrap() })
-        .filter(|row| !row.is_empty())
-        .collect();
-
-    let typed_rows: Vec<HashMap<String, Value>> = raw_rows
-        .iter()
-        .map(data::row_to_values)
-        .map(|map| data::columns_to_numbers(&numeric_columns, map))
-        .map(|map| data::columns_to_booleans(&boolean_columns, map))
-        .collect();
-
-    let mut items: Value = typed_rows
-        .iter()
-        .map(|row| -> Value {
-            let mut items = Map::new();
-
-            row.iter().for_each(|(key, value)| {
-                let (key, value) = data::dimensional_converter(key, value, ds);
-                let prepared_value = data::prepare_upsert(items.entry(key.clone()), value);
-                items.insert(key, prepared_value);
-            });
+    let infer_sample = value_t!(cli_matches, cli::INFER_SAMPLE, usize).unwrap_or_else(|e| e.exit());
+    let on_error = value_t!(cli_matches, cli::ON_ERROR, ErrorPolicy).unwrap_or_else(|e| e.exit());
+    let mut on_type_error =
+        value_t!(cli_matches, cli::ON_TYPE_ERROR, TypeErrorPolicy).unwrap_or_else(|e| e.exit());
+    let type_error_default = cli_matches
+        .value_of(cli::TYPE_ERROR_DEFAULT)
+        .map(json_value);
+    if type_error_default.is_some() && cli_matches.occurrences_of(cli::ON_TYPE_ERROR) == 0 {
+        on_type_error = TypeErrorPolicy::Default;
+    }
+    let date_output =
+        value_t!(cli_matches, cli::DATE_OUTPUT, DateOutput).unwrap_or_else(|e| e.exit());
 
-            json!(items)
-        })
-        .collect();
+    let (input, dialect, has_headers) = open_csv(&cli_matches)?;
 
-    if na {
-        items = data::group_numeric_arrays(items);
+    let mut options = ConvertOptions::new()
+        .dialect(dialect)
+        .has_headers(has_headers)
+        .flexible(cli_matches.is_present(cli::FLEXIBLE))
+        .arrays(cli_matches.is_present(cli::ARRAYS) || positional_arrays)
+        .array_mode(array_mode)
+        .boolean_columns(boolean_columns)
+        .numeric_columns(numeric_columns)
+        .integer_columns(integer_columns)
+        .float_columns(float_columns)
+        .decimal_columns(decimal_columns)
+        .on_type_error(on_type_error)
+        .type_error_default(type_error_default.unwrap_or(serde_json::Value::Null))
+        .infer_types(cli_matches.is_present(cli::INFER_TYPES))
+        .infer_sample(infer_sample)
+        .typed_headers(cli_matches.is_present(cli::TYPED_HEADERS))
+        .remove_empty_strings(cli_matches.is_present(cli::REMOVE_EMPTY_STRINGS))
+        .remove_empty_objects(cli_matches.is_present(cli::REMOVE_EMPTY_OBJECTS))
+        .remove_nulls(cli_matches.is_present(cli::REMOVE_NULLS))
+        .null_values(null_values)
+        .nullable_columns(nullable_columns)
+        .empty_as_null(cli_matches.is_present(cli::EMPTY_AS_NULL))
+        .fold(cli_matches.is_present(cli::FOLD))
+        .jsonl(cli_matches.is_present(cli::JSONL))
+        .sort_keys(cli_matches.is_present(cli::SORT_KEYS))
+        .on_error(on_error)
+        .date_output(date_output);
+    for spec in cli_matches.values_of(cli::DATE).into_iter().flatten() {
+        let (column, formats) = column_formats(spec);
+        options = options.date_column(column, formats);
+    }
+    for spec in cli_matches.values_of(cli::DATETIME).into_iter().flatten() {
+        let (column, formats) = column_formats(spec);
+        options = options.datetime_column(column, formats);
+    }
+    options = options
+        .boolean_format(boolean_format(&cli_matches))
+        .number_format(number_format(&cli_matches));
+    if let Some(timezone) = cli_matches.value_of(cli::TIMEZONE) {
+        options = options.timezone(parse_timezone(timezone).unwrap()); // Checked by clap
+    }
+    if let Some(ds) = cli_matches.value_of(cli::DIMENSIONAL_SEPARATOR) {
+        options = options.dimensional_separator(ds);
     }
-    if res {
-        items = data::remove_empty_strings(items);
+    if let Some(schema_file) = cli_matches.value_of(cli::SCHEMA) {
+        options = options.schema(Schema::from_path(schema_file)?);
     }
-    if reo {
-        items = data::remove_empty_objects(items);
+    if let Some(json_schema_file) = cli_matches.value_of(cli::VALIDATE) {
+        options = options.validate(read_json_schema(json_schema_file)?);
     }
-    if fold {
-        items = data::fold(items, csv_reader.headers().unwrap(), ds);
+    let converter = options.build();
+
+    let mut rows = converter.rows(input)?.on_warning(warn);
+    if let Some(rejects_file) = cli_matches.value_of(cli::REJECTS) {
+        let rejects = create_file(rejects_file)?;
+        rows = rows.rejects(rejects)?;
+    }
+    if let Some(json_schema_file) = cli_matches.value_of(cli::EMIT_JSON_SCHEMA) {
+        let mut file = create_file(json_schema_file)?;
+        serde_json::to_writer_pretty(&mut file, &rows.json_schema())?;
+        file.write_all(b"\n")?;
     }
 
-    if let Some(out_dir) = out_dir {
+    let summary = if let Some(out_dir) = out_dir {
         if let Some(out_name) = out_name {
             // If a template name was used.
             // With a template jsonl makes no sense.
-            let raw_rows_iter = raw_rows.into_iter();
-            let items_iter = items.as_array().unwrap().iter().cloned();
-
-            raw_rows_iter.zip(items_iter).for_each(|(raw, data)| {
-                let output = serde_json::to_string_pretty(&data).unwrap();
-                let file_name = strfmt(&out_name, &raw).unwrap();
+            for row in &mut rows {
+                let row = row?;
+                let output = serde_json::to_string_pretty(&row.value)?;
+                let file_name = strfmt(out_name, &row.raw).unwrap();
                 sys::write_json_to_file(&out_dir, &file_name, &output)
                     .expect("Failed to write to file");
-            })
+            }
+            rows.summary()
         } else {
             // If no template name was provided
-            let csv_file = match csv_file {
-                Some(csv_file) => csv_file, // use the same name as the input file
-                None => "output"            // otherwise default to output.json
-            };
-
-            let output = if jsonl {
-                items.as_array().unwrap().iter()
-                              .map(|item| serde_json::to_string(&item).unwrap())
-                              .collect::<Vec<String>>()
-                              .join("\n")
-            } else {
-                serde_json::to_string_pretty(&items).unwrap()
-            };
+            // Use the same name as the input file, otherwise default to output.json
+            let csv_file = csv_file.unwrap_or("output");
 
             let file_name = sys::get_file_name(&csv_file);
-            sys::write_json_to_file(&out_dir, &file_name, &output)
-                .expect("Failed to write to file");
+            let file =
+                sys::create_json_file(&out_dir, &file_name).expect("Failed to write to file");
+            // Don't leave half of the json behind when the conversion fails
+            converter.write_rows(rows, file).inspect_err(|_| {
+                let _ = fs::remove_file(sys::json_file_path(&out_dir, &file_name));
+            })?
         }
     } else {
         // If no output was specified
-        let output = if jsonl {
-            items.as_array().unwrap().iter()
-                          .map(|item| serde_json::to_string(&item).unwrap())
-                          .collect::<Vec<String>>()
-                          .join("\n")
-        } else {
-            serde_json::to_string_pretty(&items).unwrap()
-        };
+        let stdout = io::stdout();
+        converter.write_rows(rows, stdout.lock())?
+    };
+
+    report(&summary);
+    Ok(())
+}
+
+fn infer_schema(cli_matches: &ArgMatches) -> csv2json::Result<()> {
+    let infer_sample = value_t!(cli_matches, cli::INFER_SAMPLE, usize).unwrap_or_else(|e| e.exit());
+    let (input, dialect, has_headers) = open_csv(cli_matches)?;
+    let mut options = ConvertOptions::new()
+        .dialect(dialect)
+        .has_headers(has_headers)
+        .infer_sample(infer_sample)
+        .typed_headers(cli_matches.is_present(cli::TYPED_HEADERS));
+    if let Some(ds) = cli_matches.value_of(cli::DIMENSIONAL_SEPARATOR) {
+        options = options.dimensional_separator(ds);
+    }
+    let schema = options.build().infer_schema(input)?;
+    match cli_matches.value_of(cli::OUT) {
+        Some(schema_file) => schema.to_path(schema_file),
+        None => {
+            print!("{}", schema.to_json()?);
+            Ok(())
+        }
+    }
+}
+
+fn json_to_csv(cli_matches: &ArgMatches) -> csv2json::Result<()> {
+    let mut options = ConvertOptions::new()
+        .delimiter(delimiter(cli_matches))
+        .fold(cli_matches.is_present(cli::FOLD));
+    if let Some(ds) = cli_matches.value_of(cli::DIMENSIONAL_SEPARATOR) {
+        options = options.dimensional_separator(ds);
+    }
+    let converter = options.build();
+    let reader = open_input(cli_matches.value_of(cli::IN));
+    match cli_matches.value_of(cli::OUT) {
+        Some(csv_file) => converter.write_csv(reader, create_file(csv_file)?)?,
+        None => converter.write_csv(reader, io::stdout().lock())?,
+    };
+    Ok(())
+}
+
+// The columns given by name or glob, followed by those given by regular expression
+fn selectors(cli_matches: &ArgMatches, globs: &str, regexes: &str) -> Vec<Selector> {
+    let globs = cli_matches.values_of(globs).into_iter().flatten();
+    let regexes = cli_matches.values_of(regexes).into_iter().flatten();
+    globs
+        .map(Selector::glob)
+        .chain(regexes.map(|regex| Selector::regex(regex).unwrap())) // Checked by clap
+        .collect()
+}
+
+// Split `COLUMN=FORMAT|FORMAT` into the column and its formats
+fn column_formats(spec: &str) -> (&str, Vec<&str>) {
+    match spec.find('=') {
+        Some(index) => (&spec[..index], spec[index + 1..].split('|').collect()),
+        None => (spec, vec![]),
+    }
+}
+
+fn boolean_format(cli_matches: &ArgMatches) -> BooleanFormat {
+    let mut format = BooleanFormat::default();
+    if let Some(values) = cli_matches.values_of_lossy(cli::TRUE_VALUES) {
+        format.true_values = values;
+    }
+    if let Some(values) = cli_matches.values_of_lossy(cli::FALSE_VALUES) {
+        format.false_values = values;
+    }
+    format.strict = !cli_matches.is_present(cli::LENIENT_BOOLEANS);
+    format.empty_is_null = cli_matches.value_of(cli::EMPTY_BOOLEAN) == Some("null");
+    format
+}
+
+fn number_format(cli_matches: &ArgMatches) -> NumberFormat {
+    // Each of these has been checked by clap
+    let mut format = match cli_matches.value_of(cli::NUMBER_LOCALE) {
+        Some(locale) => NumberFormat::from_locale(locale).unwrap(),
+        None => NumberFormat::default(),
+    };
+    let separator = |name| {
+        cli_matches
+            .value_of(name)
+            .and_then(|separator| separator.chars().next())
+    };
+    if let Some(separator) = separator(cli::DECIMAL_SEPARATOR) {
+        format.decimal_separator = separator;
+    }
+    if let Some(separator) = separator(cli::THOUSANDS_SEPARATOR) {
+        format.thousands_separator = Some(separator);
+    }
+    if format.thousands_separator == Some(format.decimal_separator) {
+        clap::Error::with_description(
+            "The decimal and thousands separators must be different",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+    format.divide_percentages = cli_matches.is_present(cli::DIVIDE_PERCENTAGES);
+    format
+}
+
+// Values given on the command line are json, or a string if they are not valid json
+fn json_value(value: &str) -> serde_json::Value {
+    serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_owned()))
+}
+
+// Create a file, naming it in the error when that fails
+fn create_file(path: &str) -> io::Result<File> {
+    File::create(path)
+        .map_err(|err| io::Error::new(err.kind(), format!("Could not create {}: {}", path, err)))
+}
+
+fn read_json_schema(json_schema_file: &str) -> csv2json::Result<serde_json::Value> {
+    let contents = fs::read_to_string(json_schema_file)?;
+    serde_json::from_str(&contents)
+        .map_err(|err| csv2json::Error::Schema(format!("{}: {}", json_schema_file, err)))
+}
+
+fn dialect(cli_matches: &ArgMatches) -> Dialect {
+    // Each of these is checked by clap
+    let byte = |name| {
+        cli_matches
+            .value_of(name)
+            .map(|value| parse_byte(value).unwrap())
+    };
+    Dialect {
+        delimiter: delimiter(cli_matches),
+        quote: byte(cli::QUOTE).unwrap(), // Has a default
+        escape: byte(cli::ESCAPE),
+        double_quote: !cli_matches.is_present(cli::NO_DOUBLE_QUOTE),
+        quoting: !cli_matches.is_present(cli::NO_QUOTING),
+        comment: byte(cli::COMMENT),
+        trim: value_t!(cli_matches, cli::TRIM, Trim).unwrap_or_else(|e| e.exit()),
+        terminator: value_t!(cli_matches, cli::TERMINATOR, Terminator).unwrap_or_else(|e| e.exit()),
+    }
+}
+
+fn delimiter(cli_matches: &ArgMatches) -> u8 {
+    let delimiter = cli_matches.value_of(cli::DELIMITER).unwrap(); // Has a default
+    parse_delimiter(delimiter).unwrap() // Checked by clap
+}
+
+// The csv to read as UTF-8, its dialect and whether it has headers, which are sniffed from the
+// start of the csv with --sniff
+fn open_csv(cli_matches: &ArgMatches) -> csv2json::Result<(Box<dyn Read>, Dialect, bool)> {
+    let encoding = match cli_matches.value_of(cli::ENCODING).unwrap() {
+        "auto" => None,
+        encoding => Some(encoding.parse().unwrap()), // Checked by clap
+    };
+    let input = Decoder::new(open_input(cli_matches.value_of(cli::IN)), encoding)?;
+    match encoding {
+        None => eprintln!("Detected encoding {}", input.encoding()),
+        // The encoding defaults to utf-8, only warn when it was asked for
+        Some(encoding)
+            if encoding != input.encoding() && cli_matches.occurrences_of(cli::ENCODING) > 0 =>
+        {
+            eprintln!(
+                "Warning: the csv starts with a {0} byte order mark, so it is read as {0} rather than {1}",
+                input.encoding(),
+                encoding
+            )
+        }
+        Some(_) => {}
+    }
+    let input: Box<dyn Read> = Box::new(input);
+    let dialect = dialect(cli_matches);
+    let has_headers = !cli_matches.is_present(cli::NO_HEADERS);
+    if !cli_matches.is_present(cli::SNIFF) {
+        return Ok((input, dialect, has_headers));
+    }
+    // Options that were given are kept, only the rest is sniffed
+    let known = Known {
+        delimiter: cli_matches.occurrences_of(cli::DELIMITER) > 0,
+        quote: cli_matches.occurrences_of(cli::QUOTE) > 0,
+        terminator: cli_matches.occurrences_of(cli::TERMINATOR) > 0,
+    };
+    let (sniffed, input) = sniff_reader(input, dialect, known)?;
+    eprintln!("Sniffed {}", sniffed);
+    Ok((
+        Box::new(input),
+        sniffed.dialect,
+        has_headers && sniffed.has_headers,
+    ))
+}
+
+fn open_input(in_file: Option<&str>) -> Box<dyn Read> {
+    match in_file {
+        Some(in_file) => {
+            let file = File::open(in_file).expect("Could not read input file");
+            Box::new(file)
+        }
+        None => {
+            eprintln!("Reading from standard input, press Ctrl+D or Ctrl+C to exit.");
+            eprintln!("Use --in if you meant to specify a file.");
+            eprintln!("Use --help for usage information.");
+            Box::new(io::stdin())
+        }
+    }
+}
+
+fn warn(warning: &Warning) {
+    match warning {
+        Warning::Skipped(_) => eprintln!("{}", warning),
+        _ => eprintln!("Warning: {}", warning),
+    }
+}
 
-        println!("{}", output);
+fn report(summary: &Summary) {
+    if summary.rejected > 0 {
+        eprintln!(
+            "Dropped {} of {} rows that could not be converted",
+            summary.rejected,
+            summary.converted + summary.rejected
+        );
     }
 }
@@ -0,0 +1,290 @@
+use error::{Error, Result};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use types::{ColumnType, TypeErrorPolicy};
+
+/// Describes the columns of a csv: their types, where they go in the json and what to do when
+/// they are empty
+///
+/// Schemas can be written as json:
+///
+/// ```json
+/// {
+///   "columns": [
+///     { "name": "age", "type": "integer", "required": true },
+///     { "name": "first", "path": "name.first" },
+///     { "name": "score", "type": "float", "default": 0.0 },
+///     { "name": "joined", "type": "date", "formats": ["%d/%m/%Y", "%Y-%m-%d"] }
+///   ]
+/// }
+/// ```
+///
+/// or as toml:
+///
+/// ```toml
+/// [[columns]]
+/// name = "age"
+/// type = "integer"
+/// required = true
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    /// The separator the headers and paths are split by, used when converting without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dimensional_separator: Option<String>,
+    #[serde(default)]
+    pub columns: Vec<ColumnSchema>,
+}
+
+/// How to convert a single column
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColumnSchema {
+    /// The header of the column in the csv
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub column_type: ColumnType,
+    /// Empty values become null, unless there is a default
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub nullable: bool,
+    /// Use this instead of the header as the key in the json, this is split by the dimensional
+    /// separator just like a header would be
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// The strftime formats date and datetime values can be in, the first that matches is used
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub formats: Vec<String>,
+    /// What to do with values that do not fit the type, instead of the setting for every column
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_type_error: Option<TypeErrorPolicy>,
+    /// How many distinct values were seen, this is only a description and is not checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cardinality: Option<usize>,
+    /// Some of the values that were seen, these are only a description and are not checked
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<Value>,
+    /// The value to use when the column is empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// The csv must have this column
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub required: bool,
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+impl ColumnSchema {
+    pub fn new<S: Into<String>>(name: S, column_type: ColumnType) -> Self {
+        ColumnSchema {
+            name: name.into(),
+            column_type,
+            ..Default::default()
+        }
+    }
+
+    // What an empty value in this column should be replaced with, if anything
+    pub(crate) fn empty_value(&self) -> Option<Value> {
+        match self.default {
+            Some(ref default) => Some(default.clone()),
+            None if self.nullable => Some(Value::Null),
+            None => None,
+        }
+    }
+}
+
+impl Schema {
+    /// Read a schema from a json or toml file, depending on its extension
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let is_toml = path
+            .extension()
+            .is_some_and(|extension| extension == "toml");
+        let schema = if is_toml {
+            Self::from_toml(&contents)
+        } else {
+            Self::from_json(&contents)
+        };
+        schema.map_err(|err| match err {
+            Error::Schema(err) => Error::Schema(format!("{}: {}", path.display(), err)),
+            err => err,
+        })
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|err| Error::Schema(err.to_string()))
+    }
+
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml).map_err(|err| Error::Schema(err.to_string()))
+    }
+
+    /// Write the schema to a json or toml file, depending on its extension
+    pub fn to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let is_toml = path
+            .extension()
+            .is_some_and(|extension| extension == "toml");
+        let contents = if is_toml {
+            self.to_toml()?
+        } else {
+            self.to_json()?
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        Ok(json)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        // Numbers are serialized in a way only serde_json understands, so go through json first
+        let toml = json_to_toml(serde_json::to_value(self)?);
+        toml::to_string(&toml).map_err(|err| Error::Schema(err.to_string()))
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().rev().find(|column| column.name == name)
+    }
+
+    pub fn column_mut(&mut self, name: &str) -> &mut ColumnSchema {
+        match self.columns.iter().rposition(|column| column.name == name) {
+            Some(index) => &mut self.columns[index],
+            None => {
+                self.columns
+                    .push(ColumnSchema::new(name, ColumnType::default()));
+                self.columns.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Check the csv has every required column
+    pub fn check_headers(&self, headers: &csv::StringRecord) -> Result<()> {
+        let missing: Vec<&str> = self
+            .columns
+            .iter()
+            .filter(|column| column.required && !headers.iter().any(|h| h == column.name))
+            .map(|column| column.name.as_str())
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Schema(format!(
+                "The csv is missing required columns: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+}
+
+// Toml has no null, so nulls are left out
+fn json_to_toml(value: Value) -> Option<toml::Value> {
+    match value {
+        Value::Null => None,
+        Value::Bool(boolean) => Some(toml::Value::Boolean(boolean)),
+        Value::Number(number) => number
+            .as_i64()
+            .map(toml::Value::Integer)
+            .or_else(|| number.as_f64().map(toml::Value::Float)),
+        Value::String(string) => Some(toml::Value::String(string)),
+        Value::Array(array) => Some(toml::Value::Array(
+            array.into_iter().filter_map(json_to_toml).collect(),
+        )),
+        Value::Object(object) => Some(toml::Value::Table(
+            object
+                .into_iter()
+                .filter_map(|(key, value)| json_to_toml(value).map(|value| (key, value)))
+                .collect(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_json_and_toml() {
+        let json = Schema::from_json(
+            r#"{"columns": [
+                {"name": "age", "type": "integer", "required": true},
+                {"name": "first", "path": "name.first", "default": "unknown"}
+            ]}"#,
+        )
+        .unwrap();
+        let toml = Schema::from_toml(
+            r#"
+            [[columns]]
+            name = "age"
+            type = "integer"
+            required = true
+
+            [[columns]]
+            name = "first"
+            path = "name.first"
+            default = "unknown"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(json, toml);
+        assert_eq!(json.columns[0].column_type, ColumnType::Integer);
+        assert_eq!(json.columns[1].column_type, ColumnType::String);
+        assert_eq!(json.columns[1].path, Some(String::from("name.first")));
+    }
+
+    #[test]
+    fn it_writes_what_it_reads() {
+        let schema = Schema {
+            dimensional_separator: Some(String::from(".")),
+            columns: vec![ColumnSchema {
+                nullable: true,
+                cardinality: Some(2),
+                examples: vec![json!(1.5), json!(2)],
+                default: Some(json!(0)),
+                ..ColumnSchema::new("score.total", ColumnType::Number)
+            }],
+        };
+        assert_eq!(
+            Schema::from_json(&schema.to_json().unwrap()).unwrap(),
+            schema
+        );
+        assert_eq!(
+            Schema::from_toml(&schema.to_toml().unwrap()).unwrap(),
+            schema
+        );
+        assert!(schema
+            .to_toml()
+            .unwrap()
+            .contains("examples = [1.5, 2]\ndefault = 0\n"));
+    }
+
+    #[test]
+    fn it_rejects_unknown_types() {
+        assert!(Schema::from_json(r#"{"columns": [{"name": "a", "type": "money"}]}"#).is_err());
+    }
+
+    #[test]
+    fn it_checks_for_required_columns() {
+        let schema = Schema {
+            columns: vec![ColumnSchema {
+                required: true,
+                ..ColumnSchema::new("age", ColumnType::Integer)
+            }],
+            ..Default::default()
+        };
+        assert!(schema
+            .check_headers(&csv::StringRecord::from(vec!["age", "name"]))
+            .is_ok());
+        match schema.check_headers(&csv::StringRecord::from(vec!["name"])) {
+            Err(Error::Schema(message)) => {
+                assert_eq!(message, "The csv is missing required columns: age")
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use error::Warning;
+use regex::Regex;
+use std::fmt;
+
+/// Picks out columns by their header, with a glob or a regular expression
+///
+/// Strings are read as globs, where `*` matches any run of characters and `?` matches any one
+/// character, so a header without either is matched exactly.
+///
+/// ```
+/// use csv2json::Selector;
+///
+/// assert!(Selector::from("scores.*").matches("scores.12"));
+/// assert!(Selector::regex("^price_").unwrap().matches("price_usd"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Selector {
+    // As it was given, to describe the selector in warnings
+    source: String,
+    pattern: Pattern,
+}
+
+#[derive(Clone, Debug)]
+enum Pattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl Selector {
+    /// Match headers with a glob, see [`Selector`]
+    pub fn glob(glob: &str) -> Self {
+        let pattern = if glob.contains(['*', '?']) {
+            let mut regex = String::from("^");
+            for c in glob.chars() {
+                match c {
+                    '*' => regex.push_str(".*"),
+                    '?' => regex.push('.'),
+                    c => regex.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            regex.push('$');
+            Pattern::Regex(Regex::new(&regex).unwrap()) // Everything else is escaped
+        } else {
+            Pattern::Exact(glob.to_owned())
+        };
+        Selector {
+            source: glob.to_owned(),
+            pattern,
+        }
+    }
+
+    /// Match headers with a regular expression, which can match any part of the header unless
+    /// it is anchored with `^` and `$`
+    pub fn regex(regex: &str) -> Result<Self, String> {
+        let pattern = Regex::new(regex).map_err(|err| err.to_string())?;
+        Ok(Selector {
+            source: format!("/{}/", regex),
+            pattern: Pattern::Regex(pattern),
+        })
+    }
+
+    pub fn matches(&self, header: &str) -> bool {
+        match self.pattern {
+            Pattern::Exact(ref name) => name == header,
+            Pattern::Regex(ref regex) => regex.is_match(header),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Selector {
+    fn from(glob: &'a str) -> Self {
+        Selector::glob(glob)
+    }
+}
+
+impl From<String> for Selector {
+    fn from(glob: String) -> Self {
+        Selector::glob(&glob)
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+// The headers matched by any of the selectors, in the order of the headers. Selectors that do not
+// match any header are probably a mistake, so they are added to the warnings.
+pub fn select<'h, 's, I>(
+    selectors: I,
+    headers: &'h csv::StringRecord,
+    warnings: &mut Vec<Warning>,
+) -> Vec<&'h str>
+where
+    I: IntoIterator<Item = &'s Selector>,
+{
+    let mut selected = vec![false; headers.len()];
+    for selector in selectors {
+        let mut matched = false;
+        for (index, header) in headers.iter().enumerate() {
+            if selector.matches(header) {
+                selected[index] = true;
+                matched = true;
+            }
+        }
+        if !matched {
+            warnings.push(Warning::NoColumnsMatching(selector.to_string()));
+        }
+    }
+    headers
+        .iter()
+        .zip(selected)
+        .filter(|(_, selected)| *selected)
+        .map(|(header, _)| header)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_matches_globs() {
+        let selector = Selector::from("scores.?.*");
+        assert!(selector.matches("scores.1.total"));
+        assert!(!selector.matches("scores.10.total"));
+        assert!(!selector.matches("high_scores.1.total"));
+        assert!(Selector::from("a+b (c)").matches("a+b (c)"));
+        assert!(!Selector::from("age").matches("ages"));
+    }
+
+    #[test]
+    fn it_matches_regular_expressions() {
+        let selector = Selector::regex("^price_(usd|eur)$").unwrap();
+        assert!(selector.matches("price_usd"));
+        assert!(!selector.matches("price_gbp"));
+        assert!(Selector::regex("(").is_err());
+    }
+
+    #[test]
+    fn it_selects_headers_in_order() {
+        let headers = csv::StringRecord::from(vec!["id", "scores.1", "name", "scores.2"]);
+        let selectors = vec![Selector::from("scores.*"), Selector::from("id")];
+        let mut warnings = vec![];
+        assert_eq!(
+            select(&selectors, &headers, &mut warnings),
+            vec!["id", "scores.1", "scores.2"]
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn it_warns_about_selectors_that_match_nothing() {
+        let headers = csv::StringRecord::from(vec!["id", "name"]);
+        let selectors = vec![Selector::from("age"), Selector::regex("^n").unwrap()];
+        let mut warnings = vec![];
+        assert_eq!(select(&selectors, &headers, &mut warnings), vec!["name"]);
+        assert_eq!(
+            warnings,
+            vec![Warning::NoColumnsMatching(String::from("age"))]
+        );
+    }
+}
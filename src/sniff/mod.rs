@@ -0,0 +1,334 @@
+//! Works out the dialect of a csv from a sample of it
+//!
+//! Each delimiter and quote is tried in turn, and the one that splits the most rows into the same
+//! number of fields wins. Whether the first row is headers is decided by comparing it with the
+//! types of the rows below it.
+
+use data;
+use dialect::{self, Dialect, Terminator};
+use infer::TypeInference;
+use schema::ColumnSchema;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Cursor, Read};
+use types::{ColumnType, TypeSettings};
+
+/// How many bytes from the start of the csv are sniffed
+pub const SAMPLE_SIZE: usize = 16 * 1024;
+
+// In order of preference when they split the rows equally well
+const DELIMITERS: &[u8] = b",;\t|:";
+const QUOTES: &[u8] = b"\"'";
+
+/// Which parts of the dialect are already known, such as from options given by the user, and are
+/// kept as they are rather than sniffed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Known {
+    pub delimiter: bool,
+    pub quote: bool,
+    pub terminator: bool,
+}
+
+/// What [`sniff`] worked out about a csv
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sniffed {
+    /// The dialect it was given with the delimiter, quote and terminator that were found
+    pub dialect: Dialect,
+    /// Whether the first row looks like headers rather than values
+    pub has_headers: bool,
+}
+
+impl fmt::Display for Sniffed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "delimiter {}, quote {} and terminator {}, {} a header row",
+            dialect::byte_name(self.dialect.delimiter),
+            dialect::byte_name(self.dialect.quote),
+            self.dialect.terminator,
+            if self.has_headers { "with" } else { "without" }
+        )
+    }
+}
+
+/// Work out the delimiter, quote and terminator of the csv in `sample`, other than those that are
+/// `known`, keeping the rest of the dialect as it is, and whether it starts with headers
+///
+/// The sample is read as the whole csv, see [`sniff_reader`] for sniffing the start of a longer
+/// one. When no delimiter splits the rows into more than one field the dialect is kept.
+///
+/// ```
+/// use csv2json::sniff::{sniff, Known};
+/// use csv2json::Dialect;
+///
+/// let sniffed = sniff(b"name;price\nTea;1,50\nCake;2,00\n", Dialect::default(), Known::default());
+/// assert_eq!(sniffed.dialect.delimiter, b';');
+/// assert!(sniffed.has_headers);
+/// ```
+pub fn sniff(sample: &[u8], dialect: Dialect, known: Known) -> Sniffed {
+    let delimiters = if known.delimiter {
+        vec![dialect.delimiter]
+    } else {
+        DELIMITERS.to_vec()
+    };
+    let quotes = if known.quote {
+        vec![dialect.quote]
+    } else {
+        QUOTES.to_vec()
+    };
+    let mut best = (Consistency::default(), 0, dialect);
+    for &delimiter in &delimiters {
+        for &quote in &quotes {
+            let candidate = Dialect {
+                delimiter,
+                quote,
+                ..dialect
+            };
+            let consistency = Consistency::of(&records(candidate, sample));
+            let quoted = quoted_fields(sample, delimiter, quote);
+            if consistency.fields > 1 && (consistency, quoted) > (best.0, best.1) {
+                best = (consistency, quoted, candidate);
+            }
+        }
+    }
+    let terminator = match terminator(sample) {
+        Some(terminator) if !known.terminator => terminator,
+        _ => dialect.terminator,
+    };
+    let dialect = Dialect {
+        terminator,
+        ..best.2
+    };
+    Sniffed {
+        dialect,
+        has_headers: has_headers(&records(dialect, sample)),
+    }
+}
+
+/// A reader that reads the sniffed sample again before the rest of the csv
+pub type SniffedReader<R> = io::Chain<Cursor<Vec<u8>>, R>;
+
+/// Sniff the start of the csv in `reader`, returning a reader that still has all of the csv
+///
+/// The last line of the sample is left out as it is probably cut short, unless the csv is
+/// shorter than [`SAMPLE_SIZE`].
+pub fn sniff_reader<R: Read>(
+    mut reader: R,
+    dialect: Dialect,
+    known: Known,
+) -> io::Result<(Sniffed, SniffedReader<R>)> {
+    let mut sample = Vec::new();
+    reader
+        .by_ref()
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    let mut lines = &sample[..];
+    if sample.len() == SAMPLE_SIZE {
+        if let Some(end) = sample.iter().rposition(|&b| b == b'\n' || b == b'\r') {
+            lines = &sample[..=end];
+        }
+    }
+    let sniffed = sniff(lines, dialect, known);
+    Ok((sniffed, Cursor::new(sample).chain(reader)))
+}
+
+fn records(dialect: Dialect, sample: &[u8]) -> Vec<csv::ByteRecord> {
+    dialect
+        .reader()
+        .has_headers(false)
+        .from_reader(sample)
+        .into_byte_records()
+        .filter_map(|record| record.ok())
+        .collect()
+}
+
+// How many rows have the most common number of fields, then how many fields that is. It is only
+// counted when it is most of the rows, otherwise the rows were not split consistently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Consistency {
+    rows: usize,
+    fields: usize,
+}
+
+impl Consistency {
+    fn of(records: &[csv::ByteRecord]) -> Self {
+        let mut rows_by_fields = HashMap::new();
+        for record in records {
+            *rows_by_fields.entry(record.len()).or_insert(0) += 1;
+        }
+        rows_by_fields
+            .into_iter()
+            .map(|(fields, rows)| Consistency { rows, fields })
+            .filter(|consistency| consistency.rows * 2 > records.len())
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+// How many fields look quoted, splitting naively on the delimiter, to choose between quotes that
+// split the rows equally well
+fn quoted_fields(sample: &[u8], delimiter: u8, quote: u8) -> usize {
+    sample
+        .split(|&b| b == b'\n' || b == b'\r')
+        .flat_map(|line| line.split(move |&b| b == delimiter))
+        .filter(|field| field.len() > 1 && field[0] == quote && field[field.len() - 1] == quote)
+        .count()
+}
+
+fn terminator(sample: &[u8]) -> Option<Terminator> {
+    let crlf = sample.windows(2).any(|pair| pair == b"\r\n");
+    if crlf {
+        Some(Terminator::Crlf)
+    } else if sample.contains(&b'\n') {
+        Some(Terminator::Byte(b'\n'))
+    } else if sample.contains(&b'\r') {
+        Some(Terminator::Byte(b'\r'))
+    } else {
+        None
+    }
+}
+
+// Each column votes on whether the first row is headers. Where the rows below have a type, a
+// first value that does not fit it is a header. Where they are all strings of the same length, a
+// first value of a different length is a header. Headers are assumed unless the votes are against.
+fn has_headers(records: &[csv::ByteRecord]) -> bool {
+    let mut rows = records
+        .iter()
+        .filter_map(|record| csv::StringRecord::from_byte_record(record.clone()).ok());
+    let first = match rows.next() {
+        Some(first) => first,
+        None => return true,
+    };
+    let settings = TypeSettings::default();
+    let mut inference = TypeInference::new(&first, &settings);
+    let mut lengths = vec![HashSet::new(); first.len()];
+    for row in rows.filter(|row| row.len() == first.len()) {
+        inference.add(&row);
+        for (lengths, value) in lengths.iter_mut().zip(row.iter()) {
+            lengths.insert(value.chars().count());
+        }
+    }
+    let mut votes = 0;
+    let columns = first.iter().zip(inference.column_types()).zip(lengths);
+    for ((value, (name, column_type)), lengths) in columns {
+        if value.is_empty() {
+            continue;
+        }
+        let header = match column_type {
+            ColumnType::Null => continue,
+            ColumnType::String if lengths.len() == 1 => !lengths.contains(&value.chars().count()),
+            ColumnType::String => continue,
+            column_type => {
+                let column = ColumnSchema::new(name, column_type);
+                data::string_to_value(value, &column, &settings).is_err()
+            }
+        };
+        votes += if header { 1 } else { -1 };
+    }
+    votes >= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_sniffs_the_delimiter_and_quote() {
+        let csv = b"id|name|note\r\n1|'Smith|Jones'|'a'\r\n2|'Brown'|b\r\n";
+        let sniffed = sniff(csv, Dialect::default(), Known::default());
+        assert_eq!(
+            sniffed.dialect,
+            Dialect {
+                delimiter: b'|',
+                quote: b'\'',
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            sniffed.to_string(),
+            "delimiter |, quote ' and terminator crlf, with a header row"
+        );
+    }
+
+    #[test]
+    fn it_prefers_the_delimiter_splitting_rows_into_the_same_number_of_fields() {
+        let csv = b"name\tprice\tnote\nTea\t1,50\tgreen, loose\nCake\t2\tcarrot\n";
+        let sniffed = sniff(csv, Dialect::default(), Known::default());
+        assert_eq!(sniffed.dialect.delimiter, b'\t');
+        assert_eq!(sniffed.dialect.terminator, Terminator::Byte(b'\n'));
+    }
+
+    #[test]
+    fn it_keeps_the_dialect_of_a_single_column() {
+        let dialect = Dialect {
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        let sniffed = sniff(
+            b"# names; drinks\nname\nTea; green\n",
+            dialect,
+            Known::default(),
+        );
+        assert_eq!(
+            sniffed.dialect,
+            Dialect {
+                terminator: Terminator::Byte(b'\n'),
+                ..dialect
+            }
+        );
+    }
+
+    #[test]
+    fn it_keeps_what_is_known() {
+        let known = Known {
+            delimiter: true,
+            quote: false,
+            terminator: true,
+        };
+        let sniffed = sniff(b"id;name\r\n1;'a;b'\r\n", Dialect::default(), known);
+        assert_eq!(sniffed.dialect, Dialect::default());
+
+        let dialect = Dialect {
+            delimiter: b';',
+            terminator: Terminator::Byte(b'\n'),
+            ..Default::default()
+        };
+        let sniffed = sniff(b"id;name\r\n1;'a;b'\r\n2;'c'\r\n", dialect, known);
+        assert_eq!(
+            sniffed.dialect,
+            Dialect {
+                quote: b'\'',
+                ..dialect
+            }
+        );
+    }
+
+    #[test]
+    fn it_sniffs_whether_there_are_headers() {
+        let sniff_headers =
+            |csv: &[u8]| sniff(csv, Dialect::default(), Known::default()).has_headers;
+        assert!(sniff_headers(b"name,age,member\nann,34,true\nbob,,false\n"));
+        assert!(!sniff_headers(b"ann,34,true\nbob,27,false\n"));
+        assert!(sniff_headers(
+            b"code,country\nGB,United Kingdom\nFR,France\n"
+        ));
+        assert!(sniff_headers(b"code,country\n"));
+        assert!(!sniff_headers(
+            b"GB,United Kingdom\nFR,France\nDE,Germany\n"
+        ));
+    }
+
+    #[test]
+    fn it_sniffs_the_start_of_a_reader() {
+        let mut csv = String::from("a;b\n");
+        while csv.len() < SAMPLE_SIZE {
+            csv.push_str("1;\"2,3\"\n");
+        }
+        let (sniffed, mut reader) =
+            sniff_reader(csv.as_bytes(), Dialect::default(), Known::default()).unwrap();
+        assert_eq!(sniffed.dialect.delimiter, b';');
+        let mut read = String::new();
+        reader.read_to_string(&mut read).unwrap();
+        assert_eq!(read, csv);
+    }
+}
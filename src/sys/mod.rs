@@ -1,8 +1,8 @@
 use std::fs::File;
 use std::io::{Result, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub fn get_file_name(path: &AsRef<Path>) -> &str {
+pub fn get_file_name(path: &dyn AsRef<Path>) -> &str {
     path.as_ref()
         .file_stem()
         .expect("Could not get file name (err: file stem)")
@@ -10,16 +10,24 @@ pub fn get_file_name(path: &AsRef<Path>) -> &str {
         .expect("Could not get file name (err: str)")
 }
 
-pub fn write_json_to_file(
-    out_dir: &AsRef<Path>,
-    file_name: &AsRef<Path>,
-    data: &AsRef<[u8]>,
-) -> Result<()> {
-    let file_name = out_dir
+pub fn json_file_path(out_dir: &dyn AsRef<Path>, file_name: &dyn AsRef<Path>) -> PathBuf {
+    out_dir
         .as_ref()
         .join(file_name.as_ref())
-        .with_extension("json");
+        .with_extension("json")
+}
+
+pub fn create_json_file(out_dir: &dyn AsRef<Path>, file_name: &dyn AsRef<Path>) -> Result<File> {
+    let file_name = json_file_path(out_dir, file_name);
     eprintln!("Writing to {}", file_name.to_string_lossy());
-    let mut file = File::create(file_name)?;
-    file.write(data.as_ref()).map(|_| ())
+    File::create(file_name)
+}
+
+pub fn write_json_to_file(
+    out_dir: &dyn AsRef<Path>,
+    file_name: &dyn AsRef<Path>,
+    data: &dyn AsRef<[u8]>,
+) -> Result<()> {
+    let mut file = create_json_file(out_dir, file_name)?;
+    file.write_all(data.as_ref())
 }
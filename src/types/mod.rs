@@ -0,0 +1,322 @@
+use chrono::{DateTime, FixedOffset};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+/// The type of the values in a column
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    /// Leave the value as a string
+    #[default]
+    String,
+    /// A whole number, anything else does not fit
+    Integer,
+    /// A number that is always written with a decimal point
+    Float,
+    /// Any number, written as a whole number if it is one
+    Number,
+    /// A number written exactly as it is in the csv, without rounding
+    Decimal,
+    Boolean,
+    /// A date, written as ISO 8601 such as `2019-03-08` unless written as a timestamp
+    Date,
+    /// A date and time, written as ISO 8601 such as `2019-03-08T10:00:00` unless written as a
+    /// timestamp
+    Datetime,
+    /// The value is already json and is included as it is
+    Json,
+    /// Every value is null
+    Null,
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColumnType::String => "string",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Number => "number",
+            ColumnType::Decimal => "decimal",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Date => "date",
+            ColumnType::Datetime => "datetime",
+            ColumnType::Json => "json",
+            ColumnType::Null => "null",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ColumnType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "string" => Ok(ColumnType::String),
+            "integer" => Ok(ColumnType::Integer),
+            "float" => Ok(ColumnType::Float),
+            "number" => Ok(ColumnType::Number),
+            "decimal" => Ok(ColumnType::Decimal),
+            "boolean" => Ok(ColumnType::Boolean),
+            "date" => Ok(ColumnType::Date),
+            "datetime" => Ok(ColumnType::Datetime),
+            "json" => Ok(ColumnType::Json),
+            "null" => Ok(ColumnType::Null),
+            _ => Err(format!("Unknown column type: {}", s)),
+        }
+    }
+}
+
+/// How date and datetime columns are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DateOutput {
+    /// An ISO 8601 string
+    #[default]
+    Iso,
+    /// Seconds since the Unix epoch
+    Seconds,
+    /// Milliseconds since the Unix epoch
+    Milliseconds,
+}
+
+impl FromStr for DateOutput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "iso" => Ok(DateOutput::Iso),
+            "seconds" => Ok(DateOutput::Seconds),
+            "milliseconds" => Ok(DateOutput::Milliseconds),
+            _ => Err(format!("Unknown date output: {}", s)),
+        }
+    }
+}
+
+/// Settings shared by every date and datetime column
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DateSettings {
+    pub output: DateOutput,
+    /// The timezone of dates and times without one. Without a timezone they are written without
+    /// an offset, or as UTC when written as a timestamp.
+    pub timezone: Option<FixedOffset>,
+}
+
+/// How numbers are written in the csv
+///
+/// Currency symbols and `%` are always ignored, and numbers in parentheses are negative, as in
+/// accounting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// Separates a whole number from its decimals
+    pub decimal_separator: char,
+    /// Separates groups of digits, such as the `,` in `1,234`. If this is whitespace, any
+    /// whitespace is a separator.
+    pub thousands_separator: Option<char>,
+    /// Divide numbers ending in `%` by 100
+    pub divide_percentages: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            decimal_separator: '.',
+            thousands_separator: None,
+            divide_percentages: false,
+        }
+    }
+}
+
+impl NumberFormat {
+    /// The separators of a locale such as `de`, `fr_FR` or `de-CH`
+    pub fn from_locale(locale: &str) -> Result<Self, String> {
+        let tag = locale.to_lowercase().replace('-', "_");
+        let language = tag.split('_').next().unwrap_or_default();
+        let (decimal_separator, thousands_separator) = match (language, tag.as_str()) {
+            (_, "de_ch") | (_, "de_li") | (_, "fr_ch") | (_, "it_ch") => ('.', '\''),
+            (_, "es_mx") | (_, "pt_pt") => ('.', ','),
+            ("en", _) | ("ja", _) | ("ko", _) | ("zh", _) | ("he", _) | ("th", _) => ('.', ','),
+            ("de", _)
+            | ("da", _)
+            | ("el", _)
+            | ("es", _)
+            | ("id", _)
+            | ("it", _)
+            | ("nl", _)
+            | ("pt", _)
+            | ("ro", _)
+            | ("tr", _) => (',', '.'),
+            ("cs", _)
+            | ("fi", _)
+            | ("fr", _)
+            | ("hu", _)
+            | ("nb", _)
+            | ("no", _)
+            | ("pl", _)
+            | ("ru", _)
+            | ("sk", _)
+            | ("sv", _)
+            | ("uk", _) => (',', ' '),
+            _ => return Err(format!("Unknown number locale: {}", locale)),
+        };
+        Ok(NumberFormat {
+            decimal_separator,
+            thousands_separator: Some(thousands_separator),
+            ..Default::default()
+        })
+    }
+}
+
+/// How boolean columns are read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BooleanFormat {
+    /// The values that are true, ignoring case
+    pub true_values: Vec<String>,
+    /// The values that are false, ignoring case
+    pub false_values: Vec<String>,
+    /// Values that are neither true nor false do not fit the type, otherwise they are true. This
+    /// is the default.
+    pub strict: bool,
+    /// Empty values are null, otherwise they are false
+    pub empty_is_null: bool,
+}
+
+impl Default for BooleanFormat {
+    fn default() -> Self {
+        let values = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        BooleanFormat {
+            true_values: values(&["true", "t", "yes", "y", "on", "1"]),
+            false_values: values(&["false", "f", "no", "n", "off", "0"]),
+            strict: true,
+            empty_is_null: false,
+        }
+    }
+}
+
+/// What to do with a value that does not fit the type of its column
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TypeErrorPolicy {
+    /// The row can not be converted, see [`ErrorPolicy`](crate::ErrorPolicy)
+    #[default]
+    Fail,
+    /// Use null instead
+    Null,
+    /// Keep the value as the string it was in the csv
+    Keep,
+    /// Use the default of the column, or the default for every column if it has none
+    Default,
+}
+
+impl FromStr for TypeErrorPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(TypeErrorPolicy::Fail),
+            "null" => Ok(TypeErrorPolicy::Null),
+            "keep" => Ok(TypeErrorPolicy::Keep),
+            "default" => Ok(TypeErrorPolicy::Default),
+            _ => Err(format!("Unknown type error policy: {}", s)),
+        }
+    }
+}
+
+/// Settings for converting the values of every column
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TypeSettings {
+    /// Values that are null in every column, such as `NA`, replaced before converting types
+    pub null_values: Vec<String>,
+    pub dates: DateSettings,
+    pub numbers: NumberFormat,
+    pub booleans: BooleanFormat,
+    /// What to do with values that do not fit their type, unless their column says otherwise
+    pub on_type_error: TypeErrorPolicy,
+    /// The value used by [`TypeErrorPolicy::Default`] for columns without a default
+    pub type_error_default: Value,
+}
+
+/// Split a header such as `age:integer` into the name of the column and its type
+///
+/// Headers that do not end in the name of a type are left as they are. As well as the names of
+/// the types, `int` and `bool` can be used.
+pub fn split_typed_header(header: &str) -> (&str, Option<ColumnType>) {
+    if let Some(index) = header.rfind(':') {
+        let column_type = match header[index + 1..].trim().to_lowercase().as_str() {
+            "int" => Ok(ColumnType::Integer),
+            "bool" => Ok(ColumnType::Boolean),
+            name => name.parse(),
+        };
+        if let Ok(column_type) = column_type {
+            return (header[..index].trim_end(), Some(column_type));
+        }
+    }
+    (header, None)
+}
+
+/// Read a timezone given as `UTC` or an offset such as `+02:00` or `-0500`
+pub fn parse_timezone(timezone: &str) -> Result<FixedOffset, String> {
+    if timezone.eq_ignore_ascii_case("utc") || timezone == "Z" {
+        return Ok(FixedOffset::east_opt(0).unwrap());
+    }
+    DateTime::parse_from_str(
+        &format!("2000-01-01 00:00 {}", timezone),
+        "%Y-%m-%d %H:%M %z",
+    )
+    .map(|datetime| *datetime.offset())
+    .map_err(|_| format!("Unknown timezone: {}", timezone))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_timezones() {
+        assert_eq!(parse_timezone("UTC"), Ok(FixedOffset::east_opt(0).unwrap()));
+        assert_eq!(
+            parse_timezone("+02:00"),
+            Ok(FixedOffset::east_opt(2 * 3600).unwrap())
+        );
+        assert_eq!(
+            parse_timezone("-0530"),
+            Ok(FixedOffset::west_opt(5 * 3600 + 30 * 60).unwrap())
+        );
+        assert!(parse_timezone("Europe/London").is_err());
+    }
+
+    #[test]
+    fn it_splits_typed_headers() {
+        assert_eq!(
+            split_typed_header("age:number"),
+            ("age", Some(ColumnType::Number))
+        );
+        assert_eq!(
+            split_typed_header("active : Bool"),
+            ("active", Some(ColumnType::Boolean))
+        );
+        assert_eq!(
+            split_typed_header("a:b:json"),
+            ("a:b", Some(ColumnType::Json))
+        );
+        assert_eq!(split_typed_header("time:utc"), ("time:utc", None));
+        assert_eq!(split_typed_header("name"), ("name", None));
+    }
+
+    #[test]
+    fn it_knows_the_separators_of_locales() {
+        let separators = |locale| {
+            NumberFormat::from_locale(locale).map(|format| {
+                (
+                    format.decimal_separator,
+                    format.thousands_separator.unwrap(),
+                )
+            })
+        };
+        assert_eq!(separators("en_GB"), Ok(('.', ',')));
+        assert_eq!(separators("de"), Ok((',', '.')));
+        assert_eq!(separators("fr-FR"), Ok((',', ' ')));
+        assert_eq!(separators("de-CH"), Ok(('.', '\'')));
+        assert!(separators("xx").is_err());
+    }
+}